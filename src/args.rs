@@ -0,0 +1,53 @@
+use amethyst::{Error, prelude::Config};
+use crate::snapshot::Snapshot;
+
+const DEFAULT_SCENARIO: &str = "scenarios/rings.ron";
+
+// Command line options
+#[derive(Debug)]
+pub struct Args {
+    pub headless_ticks: Option<u64>,    // --headless <ticks>: Run only the physics for this many ticks, then exit
+    pub start: Start,
+}
+
+// What the simulation starts from
+#[derive(Debug, Clone)]
+pub enum Start {
+    Scenario(String),   // --scenario <path>: Relative to the assets folder
+    Snapshot(Snapshot), // --snapshot <path>: A snapshot saved while running
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            headless_ticks: None,
+            start: Start::Scenario(DEFAULT_SCENARIO.to_owned()),
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, Error> {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    let ticks = Self::value(&mut iter, &arg)?;
+                    args.headless_ticks = Some(ticks.parse()
+                        .map_err(|_| Error::from_string(format!("Invalid tick count for --headless: {}", ticks)))?);
+                },
+                "--scenario" => args.start = Start::Scenario(Self::value(&mut iter, &arg)?),
+                "--snapshot" => args.start = Start::Snapshot(Snapshot::load(Self::value(&mut iter, &arg)?)?),
+                _ => return Err(Error::from_string(format!("Unknown argument: {}", arg))),
+            }
+        }
+
+        Ok(args)
+    }
+
+    fn value(iter: &mut impl Iterator<Item = String>, arg: &str) -> Result<String, Error> {
+        iter.next().ok_or_else(|| Error::from_string(format!("Missing value for {}", arg)))
+    }
+}
@@ -1,50 +1,128 @@
 pub use physics::*;
 
 use amethyst::{
-    ecs::{Component, DenseVecStorage},
-    renderer::SpriteRender,
+    ecs::{Component, DenseVecStorage, Entity},
+    core::math::Point2,
+    renderer::{SpriteRender, palette::Srgba},
 };
+use std::collections::VecDeque;
+use serde::{Serialize, Deserialize};
 use crate::resources::SpriteRenders;
 
-#[derive(Component, Debug, Copy, Clone)]
+#[derive(Component, Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum BodyType {
     Planet,
     Star,
+    BlackHole,
 }
 
 impl BodyType {
     pub fn from_mass(m: f32) -> Self {
-        if m > crate::entities::body::PLANET_STAR_MASS_BOUNDARY {
+        use crate::entities::body::{PLANET_STAR_MASS_BOUNDARY, STAR_BLACK_HOLE_MASS_BOUNDARY};
+
+        if m > STAR_BLACK_HOLE_MASS_BOUNDARY {
+            BodyType::BlackHole
+        } else if m > PLANET_STAR_MASS_BOUNDARY {
             BodyType::Star
         } else {
             BodyType::Planet
         }
     }
 
-    pub fn get_render(&self, renders: &SpriteRenders) -> SpriteRender {
+    // None when running without sprites loaded, e.g. headless
+    pub fn get_render(&self, renders: &SpriteRenders) -> Option<SpriteRender> {
         match *self {
-            Self::Planet | Self::Star => renders.planet.as_ref().unwrap().clone(),
+            Self::Planet => renders.planet.clone(),
+            Self::Star => renders.star.clone(),
+            Self::BlackHole => renders.black_hole.clone(),
+        }
+    }
+
+    // Colour the sprite is tinted. Stars go from red to blue-white as they get heavier (and hotter).
+    pub fn tint(&self, mass: f32) -> Srgba {
+        use crate::entities::body::{PLANET_STAR_MASS_BOUNDARY, STAR_BLACK_HOLE_MASS_BOUNDARY};
+
+        // (Fraction of the way from lightest to heaviest star, colour)
+        const STAR_COLOURS: [(f32, (f32, f32, f32)); 4] = [
+            (0.0, (1.0, 0.45, 0.25)),   // Red dwarf
+            (0.35, (1.0, 0.8, 0.45)),   // Yellow
+            (0.65, (1.0, 0.98, 0.94)),  // White
+            (1.0, (0.62, 0.75, 1.0)),   // Blue giant
+        ];
+
+        match *self {
+            Self::Planet | Self::BlackHole => Srgba::new(1.0, 1.0, 1.0, 1.0),
+            Self::Star => {
+                // Log scale, since star masses cover a few orders of magnitude
+                let t = ((mass/PLANET_STAR_MASS_BOUNDARY).ln()/(STAR_BLACK_HOLE_MASS_BOUNDARY/PLANET_STAR_MASS_BOUNDARY).ln())
+                    .max(0.0).min(1.0);
+
+                let i = STAR_COLOURS.iter().position(|(end, _)| t <= *end).unwrap_or(STAR_COLOURS.len() - 1).max(1);
+                let ((start, c1), (end, c2)) = (STAR_COLOURS[i - 1], STAR_COLOURS[i]);
+                let f = (t - start)/(end - start);
+
+                Srgba::new(c1.0 + (c2.0 - c1.0) * f, c1.1 + (c2.1 - c1.1) * f, c1.2 + (c2.2 - c1.2) * f, 1.0)
+            },
+        }
+    }
+}
+
+// Halo drawn around a star. Kept on its own entity, since an entity can only have one sprite.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Glow {
+    pub body: Entity,
+}
+
+// Where a body has been, newest point last
+#[derive(Component, Debug, Clone)]
+pub struct Trail {
+    pub points: VecDeque<Point2<f32>>,
+    pub enabled: bool,
+}
+
+impl Trail {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            points: VecDeque::new(),
+            enabled,
+        }
+    }
+
+    // Adds a point, dropping the oldest ones once there are more than length
+    pub fn push(&mut self, point: Point2<f32>, length: usize) {
+        self.points.push_back(point);
+        while self.points.len() > length {
+            self.points.pop_front();
         }
     }
 }
 
+// What happens when bodies collide. Bodies without this use the one in the physics config.
+#[derive(Component, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum CollisionResponse {
+    Merge,  // Combine into one body
+    Bounce { restitution: f32 },    // 1 is perfectly elastic, 0 is perfectly inelastic
+}
+
 
 pub mod physics {
     use amethyst::{
-        ecs::{Component, DenseVecStorage},
+        ecs::{Component, DenseVecStorage, FlaggedStorage, NullStorage},
         core::{
-            math::{Vector2, Isometry2},
+            math::{Vector2, Point2, Isometry2},
             transform::Transform,
         },
     };
-    use ncollide2d::shape::Shape;
+    use ncollide2d::{shape::Shape, bounding_volume::AABB};
     use std::boxed::Box;
 
     #[derive(Shrinkwrap, Component, Copy, Clone)]
     #[shrinkwrap(mutable)]
     pub struct Velocity(pub Vector2<f32>);
     
+    // Flagged so BodyEvolutionSystem can tell when it changes
     #[derive(Shrinkwrap, Component)]
+    #[storage(FlaggedStorage)]
     #[shrinkwrap(mutable)]
     pub struct Mass(pub f32);
 
@@ -54,13 +132,36 @@ pub mod physics {
         }
     }
     
-    #[derive(Shrinkwrap, Component)]
+    // Gravitational acceleration at the body's current position, left over from the last physics step.
+    // Integrators reuse it as the acceleration at the start of the next step.
+    #[derive(Shrinkwrap, Component, Copy, Clone)]
     #[shrinkwrap(mutable)]
-    pub struct Force(pub Vector2<f32>); // Also acceleration. If the object has no mass, assume m = 1, so F = a
+    pub struct Acceleration(pub Vector2<f32>);
+
+    // Held still by the integrator, but still pulls on everything else
+    #[derive(Component, Default, Copy, Clone)]
+    #[storage(NullStorage)]
+    pub struct Pinned;
+
+    // Fragment of a tidal disruption. Left whole from then on, or the fragments (still inside the Roche limit)
+    // would be torn apart again every tick until they couldn't get any smaller.
+    #[derive(Component, Default, Copy, Clone)]
+    #[storage(NullStorage)]
+    pub struct TidalDebris;
+
+    // Body position at the last two physics ticks. Transforms are drawn part way between them.
+    #[derive(Component, Copy, Clone)]
+    pub struct Interpolation {
+        pub previous: Point2<f32>,
+        pub current: Point2<f32>,
+    }
 
-    impl Default for Force {
-        fn default() -> Self {
-            Self(Vector2::zeros())
+    impl Interpolation {
+        pub fn at(pos: Point2<f32>) -> Self {
+            Self {
+                previous: pos,
+                current: pos,
+            }
         }
     }
 
@@ -68,26 +169,29 @@ pub mod physics {
     pub struct Collider(pub Box<dyn Shape<f32>>);
 
     impl Collider {
-        pub fn is_colliding_with(&self, this_transform: &Transform, other: &Collider, other_transform: &Transform) -> bool {
-            use ncollide2d::query::{self, Proximity};
+        // Every body currently uses a ball collider
+        pub fn radius(&self) -> Option<f32> {
+            use ncollide2d::shape::Ball;
 
-            // Get into 2D
-            let (isometry1, isometry2) = {
-                let translation1 = this_transform.translation();
-                let vec1 = Vector2::new(translation1.x, translation1.y);
-                let angle1 = this_transform.euler_angles();
+            self.0.as_shape::<Ball<f32>>().map(|ball| ball.radius())
+        }
 
-                let translation2 = other_transform.translation();
-                let vec2 = Vector2::new(translation2.x, translation2.y);
-                let angle2 = other_transform.euler_angles();
+        // Distance from the edge of the collider to a point, 0 if it's inside
+        pub fn distance_to_point(&self, transform: &Transform, point: &Point2<f32>) -> Option<f32> {
+            self.0.as_point_query().map(|query| query.distance_to_point(&isometry_of(transform), point, true))
+        }
 
-                (Isometry2::new(vec1, angle1.0), Isometry2::new(vec2, angle2.0))
-            };
+        pub fn aabb(&self, transform: &Transform) -> AABB<f32> {
+            self.0.aabb(&isometry_of(transform))
+        }
+
+        pub fn is_colliding_with(&self, this_transform: &Transform, other: &Collider, other_transform: &Transform) -> bool {
+            use ncollide2d::query::{self, Proximity};
 
             let proximity = query::proximity(
-                &isometry1,
+                &isometry_of(this_transform),
                 &*(self.0), // gross
-                &isometry2,
+                &isometry_of(other_transform),
                 &*(other.0),
                 0.1
             );
@@ -95,4 +199,11 @@ pub mod physics {
             proximity == Proximity::Intersecting
         }
     }
-}
\ No newline at end of file
+
+    // Get into 2D
+    pub fn isometry_of(transform: &Transform) -> Isometry2<f32> {
+        let translation = transform.translation();
+        let angle = transform.euler_angles();
+        Isometry2::new(Vector2::new(translation.x, translation.y), angle.0)
+    }
+}
@@ -1,6 +1,16 @@
-
 pub mod body {
+    use amethyst::{
+        ecs::{Entity, Entities},
+        core::math::{Point2, Vector2},
+        prelude::*,
+    };
+    use rand::{Rng, rngs::ThreadRng};
     use std::f32::consts::PI;
+
+    use crate::components::*;
+    use crate::events::BodyCreationEvent;
+    use crate::systems::BodyCreationData;
+
     const PLANET_SPRITE_RADIUS: f32 = 32.0/2.0;    // Radius of default sprite = width/2.0
     pub const PLANET_SPRITE_RATIO: f32 = 1.0/PLANET_SPRITE_RADIUS;
 
@@ -9,6 +19,18 @@ pub mod body {
     pub const PLANET_STAR_MASS_BOUNDARY: f32 = 4.0/3.0 * PI * STAR_MIN_RADIUS * STAR_MIN_RADIUS * STAR_MIN_RADIUS * PLANET_DENSITY;
     pub const PLANET_DENSITY: f32 = 5000.0;
 
+    const BLACK_HOLE_MIN_STAR_RADIUS: f32 = 150.0;
+    // Point at which a star collapses into a black hole
+    pub const STAR_BLACK_HOLE_MASS_BOUNDARY: f32 = 4.0/3.0 * PI * BLACK_HOLE_MIN_STAR_RADIUS * BLACK_HOLE_MIN_STAR_RADIUS * BLACK_HOLE_MIN_STAR_RADIUS * PLANET_DENSITY;
+
+    // Radius of a body of this mass. Black holes are the size of their event horizon, everything else is PLANET_DENSITY.
+    pub fn radius_from_mass(mass: f32) -> f32 {
+        match BodyType::from_mass(mass) {
+            BodyType::BlackHole => crate::tools::schwarzschild_radius(mass),
+            BodyType::Planet | BodyType::Star => crate::tools::inverse_volume_of_sphere(mass/PLANET_DENSITY),
+        }
+    }
+
     // Returns the magnitude of the velocity (speed) needed for a circular orbit around another planet
     // Orbit is circular when the kinetic energy does not change.
     // K = GMm/2r  -- Derived from centripetal force (in circular motion) = gravitational force
@@ -17,45 +39,103 @@ pub mod body {
     // sqrt(GM/r) = v
 
 
-    // pub fn add_body_with_satellites(
-    //     world: &mut World,
-    //     sprite_renders: &SpriteRender,
-    //     rand_thread: &mut ThreadRng,
-    //     position: Point2<f32>,
-    //     velocity: Vector2<f32>,
-    
-    //     main_body_radius: f32,
-    //     satellite_num: usize,
-    //     satellite_orbit_radius_range: (f32, f32),    // Starting from surface of body
-    //     satellite_body_radius_range: (f32, f32),
-    //     orbit_direction_clockwise: bool,  // anticlockwise = false, clockwise = true
-    // ) {
-    //     self::add_body(world.create_entity(), sprite_render, position.clone(), velocity.clone(), main_body_radius);  // Add main planet
+    pub fn add_body(
+        world: &mut World,
+        pos: Point2<f32>,
+        vel: Vector2<f32>,
+        radius: f32,
+    ) -> Entity {
+        let mass = Mass::from_radius(radius, PLANET_DENSITY).0;
+
+        create_body(world, BodyCreationEvent {
+            body_type: BodyType::from_mass(mass),
+            position: pos,
+            velocity: vel,
+            mass,
+            radius,
+            collision_response: None,
+            tidal_debris: false,
+        })
+    }
+
+    // Splits a body into equal fragments spread evenly around a circle, all moving outwards at the same speed.
+    // Being symmetric, their centre of mass and total momentum are the same as the original body's.
+    pub fn debris_cloud(
+        rand_thread: &mut ThreadRng,
+        position: Point2<f32>,
+        velocity: Vector2<f32>,
+        mass: f32,
+        fragment_num: usize,
+        speed: f32,     // Outwards, relative to velocity
+    ) -> Vec<BodyCreationEvent> {
+        let fragment_mass = mass/fragment_num as f32;
+        let fragment_radius = crate::tools::inverse_volume_of_sphere(fragment_mass/PLANET_DENSITY);
+
+        // Far enough out that neighbouring fragments don't touch, or they would merge straight back together
+        let spacing = PI * 2.0/fragment_num as f32;
+        let cloud_radius = 1.1 * fragment_radius/(spacing/2.0).sin();
+        let start_angle = rand_thread.gen_range(0.0, spacing);
+
+        (0..fragment_num).map(|i| {
+            let angle = start_angle + spacing * i as f32;
+            let direction = Vector2::new(angle.cos(), angle.sin());
+
+            BodyCreationEvent {
+                body_type: BodyType::from_mass(fragment_mass),
+                position: position + direction * cloud_radius,
+                velocity: velocity + direction * speed,
+                mass: fragment_mass,
+                radius: fragment_radius,
+                collision_response: None,
+                tidal_debris: false,
+            }
+        }).collect()
+    }
+
+    pub fn create_body(world: &mut World, creation_event: BodyCreationEvent) -> Entity {
+        world.exec(|(mut entities, mut body_creation_data): (Entities<'_>, BodyCreationData<'_>)| {
+            creation_event.build_entity(&mut entities, &mut body_creation_data)
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_body_with_rings(
+        world: &mut World,
+        rand_thread: &mut ThreadRng,
+        position: Point2<f32>,
+        velocity: Vector2<f32>,
     
-    //     let main_body_mass = crate::tools::volume_of_sphere(main_body_radius) * PLANET_DENSITY;
-    //     let frame_velocity = velocity;
+        main_body_radius: f32,
+        moon_num: usize,
+        moon_orbit_radius_range: (f32, f32),    // Starting from surface of body
+        moon_body_radius_range: (f32, f32),
+        orbit_direction_clockwise: bool,  // anticlockwise = false, clockwise = true
+    ) {
+        add_body(world, position, velocity, main_body_radius);  // Add main body
     
-    //     for _ in 0..satellite_num {
-    //         let orbit_radius = main_body_radius + rand_thread.gen_range(satellite_orbit_radius_range.0, satellite_orbit_radius_range.1);
-    //         let orbit_speed = self::circular_orbit_speed(main_body_mass, orbit_radius);
-    //         let start_angle = rand_thread.gen_range(0.0, PI * 2.0);      // Angle from main body to satellite
-    //         let start_pos = Point2::new(orbit_radius * start_angle.cos(), orbit_radius * start_angle.sin());   // Position on circle orbit where satellite will start
-
-    //         let vel_angle = if orbit_direction_clockwise {
-    //             start_angle + PI/2.0
-    //         } else {
-    //             start_angle - PI/2.0
-    //         };
-    //         let start_velocity = Vector2::new(orbit_speed * vel_angle.cos(), orbit_speed * vel_angle.sin());
-    //         let satellite_radius = rand_thread.gen_range(satellite_body_radius_range.0, satellite_body_radius_range.1);
+        let main_body_mass = crate::tools::volume_of_sphere(main_body_radius) * PLANET_DENSITY;
+        let frame_velocity = velocity;
     
-    //         self::add_body(
-    //             world.create_entity(),
-    //             sprite_render,
-    //             Point2::new(position.x + start_pos.x, position.y + start_pos.y),
-    //             start_velocity + frame_velocity,  // Add velocity of main body
-    //             satellite_radius,
-    //         );
-    //     }
-    // }
+        for _ in 0..moon_num {
+            let orbit_radius = main_body_radius + rand_thread.gen_range(moon_orbit_radius_range.0, moon_orbit_radius_range.1);
+            let orbit_speed = crate::tools::circular_orbit_speed(main_body_mass, orbit_radius);
+            let start_angle = rand_thread.gen_range(0.0, PI * 2.0);      // Angle from main body to moon
+            let start_pos = Point2::new(orbit_radius * start_angle.cos(), orbit_radius * start_angle.sin());   // Position on circle orbit where body will start
+
+            let vel_angle = if orbit_direction_clockwise {
+                start_angle + PI/2.0
+            } else {
+                start_angle - PI/2.0
+            };
+            let start_velocity = Vector2::new(orbit_speed * vel_angle.cos(), orbit_speed * vel_angle.sin());
+            let moon_radius = rand_thread.gen_range(moon_body_radius_range.0, moon_body_radius_range.1);
+
+            add_body(
+                world,
+                Point2::new(position.x + start_pos.x, position.y + start_pos.y),
+                start_velocity + frame_velocity,  // Add velocity of main body
+                moon_radius,
+            );
+        }
+    }
 }
@@ -13,6 +13,47 @@ use crate::systems::BodyCreationData;
 #[derive(Debug)]
 pub struct CollisionEvent {
     pub group: HashSet<Entity>,
+    pub pairs: Vec<(Entity, Entity)>,   // Each pair in the group that actually touched
+    pub contact_fraction: f32,  // How far through the tick the group first touched, from 0 to 1
+}
+
+impl CollisionEvent {
+    pub fn add_contact(&mut self, e1: Entity, e2: Entity, contact_fraction: f32) {
+        self.pairs.push((e1, e2));
+        self.contact_fraction = self.contact_fraction.min(contact_fraction);
+    }
+}
+
+// A body has strayed inside the Roche limit of a more massive one, and is about to be torn apart
+#[derive(Debug)]
+pub struct TidalDisruptionEvent {
+    pub body: Entity,
+    pub primary: Entity,    // The body doing the tearing
+    pub fragment_num: usize,
+}
+
+// Change made to a body by hand, applied by BodyEditSystem
+#[derive(Debug)]
+pub struct BodyEditEvent {
+    pub body: Entity,
+    pub edit: BodyEdit,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum BodyEdit {
+    Move(Point2<f32>),
+    Resize(f32),    // Multiplies the radius, keeping the same density
+    SetVelocity(Vector2<f32>),
+    TogglePin,
+    Delete,
+}
+
+// A body's mass has taken it over one of the boundaries between body types, e.g. a planet becoming a star
+#[derive(Debug, Copy, Clone)]
+pub struct BodyTypeChanged {
+    pub body: Entity,
+    pub from: BodyType,
+    pub to: BodyType,
 }
 
 #[derive(Debug)]
@@ -22,6 +63,8 @@ pub struct BodyCreationEvent {
     pub velocity: Vector2<f32>,
     pub mass: f32,
     pub radius: f32,
+    pub collision_response: Option<CollisionResponse>, // Uses the global one from the physics config if None
+    pub tidal_debris: bool,
 }
 
 impl BodyCreationEvent {
@@ -33,21 +76,36 @@ impl BodyCreationEvent {
         use ncollide2d::shape::Ball;
         use crate::entities::body::PLANET_SPRITE_RATIO;
 
-        let scale = PLANET_SPRITE_RATIO * self.radius;
+        // A black hole is only as big as its horizon, whatever radius it was asked for
+        let radius = match self.body_type {
+            BodyType::BlackHole => crate::tools::schwarzschild_radius(self.mass),
+            BodyType::Planet | BodyType::Star => self.radius,
+        };
+
+        let scale = PLANET_SPRITE_RATIO * radius;
         let mut transform = Transform::default();
         transform.set_translation_xyz(self.position.x, self.position.y, 0.0);
         transform.set_scale(Vector3::new(scale, scale, 1.0));
 
-        let render = self.body_type.get_render(&(*body_system_data.renders_resource));
-
-        entities.build_entity()
-            .with(render, &mut body_system_data.render_storage)
+        let entity = entities.build_entity()
             .with(self.body_type, &mut body_system_data.body_type)
             .with(transform, &mut body_system_data.transforms)
             .with(Velocity(self.velocity), &mut body_system_data.velocities)
             .with(Mass(self.mass), &mut body_system_data.masses)
-            .with(Force::default(), &mut body_system_data.forces)
-            .with(Collider(Box::new(Ball::new(self.radius))), &mut body_system_data.colliders)
-            .build()
+            .with(Collider(Box::new(Ball::new(radius))), &mut body_system_data.colliders)
+            .build();
+
+        if let Some(collision_response) = self.collision_response {
+            body_system_data.collision_responses.insert(entity, collision_response).expect("Could not insert collision response.");
+        }
+        if self.tidal_debris {
+            body_system_data.tidal_debris.insert(entity, TidalDebris).expect("Could not insert tidal debris.");
+        }
+
+        if let Some(render) = self.body_type.get_render(&body_system_data.renders_resource) {
+            body_system_data.render_storage.insert(entity, render).expect("Could not insert sprite render.");
+        }
+
+        entity
     }
 }
\ No newline at end of file
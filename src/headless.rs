@@ -0,0 +1,96 @@
+use amethyst::{
+    prelude::*,
+    assets::Completion,
+    ecs::Join,
+};
+
+use crate::components::Mass;
+use crate::resources::SimClock;
+use crate::scenario::{self, ScenarioLoader};
+use crate::args::Start;
+use crate::systems::PhysicsDispatcher;
+use std::{cell::Cell, rc::Rc};
+
+// Ticks run per frame. There is nothing to draw, so just go as fast as possible.
+const TICKS_PER_UPDATE: u64 = 100;
+const LOG_INTERVAL: u64 = 1000;
+
+// Runs only the physics for a set number of ticks, then quits. No window, rendering or input.
+pub struct HeadlessState {
+    ticks: u64,
+    end_tick: u64,  // Counted from the tick the run starts at, which isn't 0 when starting from a snapshot
+    physics_dispatcher: Option<PhysicsDispatcher>,
+    start: Start,
+    scenario: Option<ScenarioLoader>,   // Until the scenario has been spawned
+    failed: Rc<Cell<bool>>,     // Shared with main, so the process can exit with an error
+}
+
+impl SimpleState for HeadlessState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+
+        self.physics_dispatcher = Some(PhysicsDispatcher::new(world));
+        self.scenario = scenario::start(world, &self.start);
+        self.end_tick = world.read_resource::<SimClock>().tick + self.ticks;
+
+        info!("Running headless for {} ticks.", self.ticks);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let StateData { world, .. } = data;
+
+        if let Some(scenario) = self.scenario.as_ref() {
+            match scenario.try_spawn(world) {
+                Completion::Complete => self.scenario = None,
+                Completion::Failed => {
+                    self.failed.set(true);
+                    return Trans::Quit;
+                },
+                Completion::Loading => return Trans::None,
+            }
+        }
+
+        let physics_dispatcher = self.physics_dispatcher.as_mut().unwrap();
+
+        for _ in 0..TICKS_PER_UPDATE {
+            let tick = world.read_resource::<SimClock>().tick;
+            if tick >= self.end_tick {
+                break;
+            }
+
+            physics_dispatcher.tick(world);
+
+            if (tick + 1) % LOG_INTERVAL == 0 {
+                Self::log_progress(world);
+            }
+        }
+
+        if world.read_resource::<SimClock>().tick >= self.end_tick {
+            info!("Finished headless run.");
+            Self::log_progress(world);
+            Trans::Quit
+        } else {
+            Trans::None
+        }
+    }
+}
+
+impl HeadlessState {
+    pub fn new(ticks: u64, start: Start, failed: Rc<Cell<bool>>) -> Self {
+        Self {
+            ticks,
+            end_tick: ticks,
+            physics_dispatcher: None,
+            start,
+            scenario: None,
+            failed,
+        }
+    }
+
+    fn log_progress(world: &World) {
+        let sim_clock = world.read_resource::<SimClock>();
+        let body_count = (&world.read_storage::<Mass>()).join().count();
+
+        info!("Tick {}, {:.2}s simulated, {} bodies.", sim_clock.tick, sim_clock.elapsed, body_count);
+    }
+}
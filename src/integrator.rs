@@ -0,0 +1,114 @@
+use amethyst::core::math::{Point2, Vector2};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Copy, Clone)]
+pub struct BodyState {
+    pub position: Point2<f32>,
+    pub velocity: Vector2<f32>,
+}
+
+// Returns the acceleration of every body when they are at the given positions.
+pub type AccelerationField<'a> = dyn Fn(&[Point2<f32>]) -> Vec<Vector2<f32>> + 'a;
+
+pub trait Integrator {
+    // Advances every body by dt.
+    // On entry `accelerations` holds the acceleration of each body at its current position.
+    // On exit it must hold the acceleration at the new position, so it can be reused by the next step.
+    fn step(&self, bodies: &mut [BodyState], accelerations: &mut Vec<Vector2<f32>>, dt: f32, field: &AccelerationField);
+}
+
+// Chosen in config/physics.ron
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum IntegratorKind {
+    SemiImplicitEuler,
+    VelocityVerlet,
+    RungeKutta4,
+}
+
+impl IntegratorKind {
+    pub fn integrator(self) -> &'static dyn Integrator {
+        match self {
+            Self::SemiImplicitEuler => &SemiImplicitEuler,
+            Self::VelocityVerlet => &VelocityVerlet,
+            Self::RungeKutta4 => &RungeKutta4,
+        }
+    }
+}
+
+// v += a dt, then x += v dt.
+// First order, and the energy error grows whenever dt changes between steps.
+pub struct SemiImplicitEuler;
+
+impl Integrator for SemiImplicitEuler {
+    fn step(&self, bodies: &mut [BodyState], accelerations: &mut Vec<Vector2<f32>>, dt: f32, field: &AccelerationField) {
+        for (body, acceleration) in bodies.iter_mut().zip(accelerations.iter()) {
+            body.velocity += acceleration * dt;
+            body.position += body.velocity * dt;
+        }
+
+        *accelerations = field(&positions(bodies));
+    }
+}
+
+// Kick-drift-kick leapfrog. Symplectic, so orbits don't spiral in or out over long runs,
+// and only needs one field evaluation per step since the last kick's acceleration is reused.
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn step(&self, bodies: &mut [BodyState], accelerations: &mut Vec<Vector2<f32>>, dt: f32, field: &AccelerationField) {
+        let half_dt = dt/2.0;
+
+        // Kick + drift
+        for (body, acceleration) in bodies.iter_mut().zip(accelerations.iter()) {
+            body.velocity += acceleration * half_dt;
+            body.position += body.velocity * dt;
+        }
+
+        *accelerations = field(&positions(bodies));
+
+        // Kick
+        for (body, acceleration) in bodies.iter_mut().zip(accelerations.iter()) {
+            body.velocity += acceleration * half_dt;
+        }
+    }
+}
+
+// Classic fourth order Runge-Kutta. Very accurate per step, but not symplectic, and needs four field evaluations.
+pub struct RungeKutta4;
+
+impl Integrator for RungeKutta4 {
+    fn step(&self, bodies: &mut [BodyState], accelerations: &mut Vec<Vector2<f32>>, dt: f32, field: &AccelerationField) {
+        let half_dt = dt/2.0;
+
+        // k1 is the derivative at the start of the step (velocity, acceleration)
+        let k1_v: Vec<Vector2<f32>> = bodies.iter().map(|body| body.velocity).collect();
+        let k1_a = accelerations.clone();
+
+        let stage = |k_v: &[Vector2<f32>], k_a: &[Vector2<f32>], h: f32| -> (Vec<Point2<f32>>, Vec<Vector2<f32>>) {
+            bodies.iter()
+                .zip(k_v.iter().zip(k_a.iter()))
+                .map(|(body, (v, a))| (body.position + v * h, body.velocity + a * h))
+                .unzip()
+        };
+
+        let (k2_x, k2_v) = stage(&k1_v, &k1_a, half_dt);
+        let k2_a = field(&k2_x);
+
+        let (k3_x, k3_v) = stage(&k2_v, &k2_a, half_dt);
+        let k3_a = field(&k3_x);
+
+        let (k4_x, k4_v) = stage(&k3_v, &k3_a, dt);
+        let k4_a = field(&k4_x);
+
+        for (i, body) in bodies.iter_mut().enumerate() {
+            body.position += (k1_v[i] + 2.0 * k2_v[i] + 2.0 * k3_v[i] + k4_v[i]) * (dt/6.0);
+            body.velocity += (k1_a[i] + 2.0 * k2_a[i] + 2.0 * k3_a[i] + k4_a[i]) * (dt/6.0);
+        }
+
+        *accelerations = field(&positions(bodies));
+    }
+}
+
+fn positions(bodies: &[BodyState]) -> Vec<Point2<f32>> {
+    bodies.iter().map(|body| body.position).collect()
+}
@@ -7,18 +7,25 @@ mod entities;
 mod resources;
 mod tools;
 mod events;
+mod quadtree;
+mod integrator;
+mod args;
+mod headless;
+mod scenario;
+mod snapshot;
 
 use amethyst::{
     core::{
-        math::{Vector2, Point2, Vector3},
         transform::{TransformBundle, Transform},
+        frame_limiter::FrameRateLimitStrategy,
         Time,
     },
     prelude::*,
     renderer::{
         // light::Light,
         Camera,
-        plugins::{RenderFlat2D, RenderToWindow},
+        plugins::{RenderFlat2D, RenderToWindow, RenderDebugLines},
+        debug_drawing::DebugLinesParams,
         types::DefaultBackend,
         RenderingBundle,
         SpriteSheet, SpriteSheetFormat, Texture, SpriteRender,
@@ -27,76 +34,90 @@ use amethyst::{
     ui::{UiFinder, UiText, UiBundle, UiCreator, RenderUi},
     input::{StringBindings, InputBundle},
     ecs::{Entity},
-    assets::{AssetStorage, Loader, Handle, ProgressCounter},
+    assets::{AssetStorage, Loader, Handle, ProgressCounter, Completion, Processor},
     utils::{application_root_dir, fps_counter},
 };
-use rand::rngs::ThreadRng;
-use rand::Rng;
 
 const CAMERA_DIMS: (f32, f32) = (1920.0, 1080.0);
 
 struct MainState {
     progress_counter: ProgressCounter,
     fps_display: Option<Entity>,
+    diagnostics_display: Option<Entity>,
+    sim_rate_display: Option<Entity>,
     sprite_sheet: Option<Handle<SpriteSheet>>,
+    physics_dispatcher: Option<systems::PhysicsDispatcher>,
+    start: args::Start,
+    scenario: Option<scenario::ScenarioLoader>, // Until the scenario has been spawned
 }
 
 impl SimpleState for MainState {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
         let world = data.world;
-        let mut rand_thread = rand::thread_rng();
 
+        self.physics_dispatcher = Some(systems::PhysicsDispatcher::new(world));
         Self::init_camera(world);
         self.sprite_sheet = Some(self.load_spritesheet(world));
 
+        let sprite_sheet = self.sprite_sheet.as_ref().unwrap();
+        let sprite = |sprite_number| Some(SpriteRender {
+            sprite_sheet: sprite_sheet.clone(),
+            sprite_number,
+        });
         world.insert(resources::SpriteRenders {
-            planet: Some(SpriteRender {
-                sprite_sheet: self.sprite_sheet.as_ref().unwrap().clone(),
-                sprite_number: 0,
-            }),
+            planet: sprite(0),
+            black_hole: sprite(1),
+            star: sprite(2),
+            glow: sprite(3),
         });
         world.insert(resources::MouseInfo::default());
+        world.insert(resources::CameraInfo::default());
+        world.insert(resources::Selection::default());
+        world.insert(DebugLinesParams { line_width: 1.5 });
 
         world.exec(|mut creator: UiCreator<'_>| {
             creator.create("ui/fps.ron", &mut self.progress_counter);
+            creator.create("ui/diagnostics.ron", &mut self.progress_counter);
+            creator.create("ui/inspector.ron", &mut self.progress_counter);
+            creator.create("ui/sim_rate.ron", &mut self.progress_counter);
         });
 
-
-        // let dist = 300.0;
-        // self.add_body(world, Point2::new(CAMERA_DIMS.0/2.0 - dist/2.0, CAMERA_DIMS.1/2.0 + 100.0), Vector2::zeros(), 30.0);
-        // self.add_body(world, Point2::new(CAMERA_DIMS.0/2.0 - dist/2.0, CAMERA_DIMS.1/2.0 - 100.0), Vector2::zeros(), 30.0);
-
-        // self.add_body(world, Point2::new(CAMERA_DIMS.0/2.0 + dist/2.0, CAMERA_DIMS.1/2.0 + 100.0), Vector2::zeros(), 30.0);
-        // self.add_body(world, Point2::new(CAMERA_DIMS.0/2.0 + dist/2.0, CAMERA_DIMS.1/2.0 - 100.0), Vector2::zeros(), 30.0);
-
-
-        // self.add_body(
-        //     world,
-        //     Point2::new(CAMERA_DIMS.0/2.0, CAMERA_DIMS.1/2.0 + (dist.powi(2) - (dist/2.0).powi(2)).sqrt()), // Using pythag
-        //     Vector2::zeros(),
-        //     30.0
-        // );
-        self.add_body_with_rings(
-            world,
-            &mut rand_thread,
-            Point2::new(CAMERA_DIMS.0/2.0, CAMERA_DIMS.1/2.0),
-            Vector2::zeros(),
-            50.0,
-            200,
-            (20.0, 200.0),
-            (0.7, 1.8),
-            true,
-        );
+        self.scenario = scenario::start(world, &self.start);
     }
 
     fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
         let StateData { world, .. } = data;
 
-        if self.fps_display.is_none() {
-            world.exec(|finder: UiFinder| {
-                if let Some(entity) = finder.find("fps") {
-                    self.fps_display = Some(entity);
+        if let Some(scenario) = self.scenario.as_ref() {
+            match scenario.try_spawn(world) {
+                Completion::Complete => self.scenario = None,
+                Completion::Failed => return Trans::Quit,
+                Completion::Loading => {},
+            }
+        }
+
+        // Run as many fixed physics ticks as this frame's time is worth
+        if self.scenario.is_none() {
+            let frame_dt = world.read_resource::<Time>().delta_real_seconds();
+            let ticks = world.write_resource::<resources::SimClock>().ticks_for_frame(frame_dt);
+            let paused = world.read_resource::<resources::SimClock>().paused;
+            if let Some(physics_dispatcher) = self.physics_dispatcher.as_mut() {
+                for _ in 0..ticks {
+                    physics_dispatcher.tick(world);
+                }
+
+                // Planets thrown and bodies resized while paused still show up straight away
+                if paused && ticks == 0 {
+                    physics_dispatcher.update_bodies(world);
                 }
+            }
+        }
+
+        if self.fps_display.is_none() || self.diagnostics_display.is_none() || self.sim_rate_display.is_none() {
+            world.exec(|finder: UiFinder| {
+                self.fps_display = finder.find("fps");
+                self.diagnostics_display = finder.find("diagnostics");
+                self.sim_rate_display = finder.find("sim_rate");
             });
         }
 
@@ -109,6 +130,23 @@ impl SimpleState for MainState {
                     fps_display.text = format!("FPS: {:.2}", fps);
                 }
             }
+
+            if let Some(diagnostics_display) = self.diagnostics_display.and_then(|entity| ui_text.get_mut(entity)) {
+                if world.read_resource::<Time>().frame_number() % 20 == 0 {
+                    let diagnostics = world.read_resource::<resources::Diagnostics>();
+                    diagnostics_display.text = format!(
+                        "dE: {:+.2e}  dp: {:.2e}  dL: {:+.2e}",
+                        diagnostics.energy_drift(),
+                        diagnostics.momentum_drift(),
+                        diagnostics.angular_momentum_drift(),
+                    );
+                }
+            }
+
+            // Every frame, so pausing shows up straight away
+            if let Some(sim_rate_display) = self.sim_rate_display.and_then(|entity| ui_text.get_mut(entity)) {
+                sim_rate_display.text = world.read_resource::<resources::SimClock>().rate_text();
+            }
         }
 
         Trans::None
@@ -116,11 +154,16 @@ impl SimpleState for MainState {
 }
 
 impl MainState {
-    fn new() -> Self {
+    fn new(start: args::Start) -> Self {
         Self {
             progress_counter: ProgressCounter::default(),
             fps_display: None,
+            diagnostics_display: None,
+            sim_rate_display: None,
             sprite_sheet: None,
+            physics_dispatcher: None,
+            start,
+            scenario: None,
         }
     }
 
@@ -155,96 +198,43 @@ impl MainState {
             &sprite_sheet_store,
         )
     }
-
-    fn add_body(
-        &self,
-        world: &mut World,
-        pos: Point2<f32>,
-        vel: Vector2<f32>,
-        radius: f32,
-    ) {
-        use components::*;
-        use ncollide2d::shape::Ball;
-
-        let scale = radius * entities::body::PLANET_SPRITE_RATIO;
-        let scale_vec = Vector3::new(scale, scale, 0.0);
-
-        let render = SpriteRender {
-            sprite_sheet: self.sprite_sheet.as_ref().unwrap().clone(),
-            sprite_number: 0,
-        };
-
-        let mut transform = Transform::default();
-        transform.set_translation_xyz(pos.x, pos.y, 0.0);
-        transform.set_scale(scale_vec);
-
-        let mass = Mass::from_radius(radius, entities::body::PLANET_DENSITY);
-
-        world.create_entity()
-            .with(BodyType::from_mass(mass.0))
-            .with(transform)
-            .with(render.clone())
-            .with(Velocity(vel))
-            .with(Force::default())
-            .with(mass)
-            .with(Collider(Box::new(Ball::new(radius))))
-            .build();
-    }
-
-    pub fn add_body_with_rings(
-        &self,
-        world: &mut World,
-        rand_thread: &mut ThreadRng,
-        position: Point2<f32>,
-        velocity: Vector2<f32>,
-    
-        main_body_radius: f32,
-        moon_num: usize,
-        moon_orbit_radius_range: (f32, f32),    // Starting from surface of body
-        moon_body_radius_range: (f32, f32),
-        orbit_direction_clockwise: bool,  // anticlockwise = false, clockwise = true
-    ) {
-        use std::f32::consts::PI;
-
-        self.add_body(world, position.clone(), velocity.clone(), main_body_radius);  // Add main body
-    
-        let main_body_mass = tools::volume_of_sphere(main_body_radius) * entities::body::PLANET_DENSITY;
-        let frame_velocity = velocity;
-    
-        for _ in 0..moon_num {
-            let orbit_radius = main_body_radius + rand_thread.gen_range(moon_orbit_radius_range.0, moon_orbit_radius_range.1);
-            let orbit_speed = tools::circular_orbit_speed(main_body_mass, orbit_radius);
-            let start_angle = rand_thread.gen_range(0.0, PI * 2.0);      // Angle from main body to moon
-            let start_pos = Point2::new(orbit_radius * start_angle.cos(), orbit_radius * start_angle.sin());   // Position on circle orbit where body will start
-
-            let vel_angle = if orbit_direction_clockwise {
-                start_angle + PI/2.0
-            } else {
-                start_angle - PI/2.0
-            };
-            let start_velocity = Vector2::new(orbit_speed * vel_angle.cos(), orbit_speed * vel_angle.sin());
-            let moon_radius = rand_thread.gen_range(moon_body_radius_range.0, moon_body_radius_range.1);
-
-            self.add_body(
-                world,
-                Point2::new(position.x + start_pos.x, position.y + start_pos.y),
-                start_velocity + frame_velocity,  // Add velocity of main body
-                moon_radius,
-            );
-        }
-    }
 }
 
 
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
 
+    let args = args::Args::parse()?;
     let app_root = application_root_dir()?;
 
     let assets_dir = app_root.join("assets");
     let config_dir = app_root.join("config");
     let display_config_path = config_dir.join("display.ron");
     let bindings_path = config_dir.join("bindings.ron");
+    let physics_config = resources::PhysicsConfig::load(config_dir.join("physics.ron"))?;
+    let trail_config = resources::TrailConfig::load(config_dir.join("trails.ron"))?;
+    info!("Physics config: {:?}", physics_config);
+    let sim_clock = resources::SimClock::new(physics_config.timestep, physics_config.max_substeps);
+
+    if let Some(ticks) = args.headless_ticks {
+        // Nothing but the physics dispatcher, which the state owns. Build with `--features empty` to drop the GPU backend.
+        let game_data = GameDataBuilder::default()
+            .with(Processor::<scenario::Scenario>::new(), "scenario_processor", &[]);
+
+        let failed = std::rc::Rc::new(std::cell::Cell::new(false));
+        let mut game = Application::build(assets_dir, headless::HeadlessState::new(ticks, args.start, failed.clone()))?
+            .with_frame_limit(FrameRateLimitStrategy::Unlimited, 0)
+            .with_resource(sim_clock)
+            .with_resource(physics_config)
+            .build(game_data)?;
+        game.run();
+
+        // Fail the run (e.g. a CI job) if the scenario couldn't be loaded
+        if failed.get() {
+            return Err(amethyst::Error::from_string("Headless run failed to load its scenario."));
+        }
+        return Ok(());
+    }
 
     let game_data = GameDataBuilder::default()
         .with_bundle(
@@ -254,23 +244,36 @@ fn main() -> amethyst::Result<()> {
                         .with_clear([0.0, 0.0, 0.0, 1.0]),
                 )
                 .with_plugin(RenderFlat2D::default())
+                .with_plugin(RenderDebugLines::default())
                 .with_plugin(RenderUi::default()),
         )?
-        .with_bundle(TransformBundle::new())?
-        .with_bundle(fps_counter::FpsCounterBundle)?
         .with_bundle(InputBundle::<StringBindings>::new()
             .with_bindings_from_file(bindings_path)?)?
+        .with(systems::TransformInterpolationSystem, "transform_interpolation_system", &[])
+        .with(systems::CameraControlSystem::default(), "camera_control_system", &["input_system", "transform_interpolation_system"])
+        .with(systems::BodyTintSystem, "body_tint_system", &[])
+        .with(systems::GlowSystem, "glow_system", &["transform_interpolation_system", "body_tint_system"])
+        .with_bundle(TransformBundle::new().with_dep(&["transform_interpolation_system", "glow_system", "camera_control_system"]))?
+        .with_bundle(fps_counter::FpsCounterBundle)?
         .with_bundle(UiBundle::<StringBindings>::new())?
 
-        .with(systems::InputParsingSystem, "input_parsing_system", &[])
-        .with(systems::physics::GravitySystem, "gravity_system", &[])
-        .with(systems::physics::ForceSystem, "force_system", &["gravity_system"])
-        .with(systems::physics::VelocitySystem, "velocity_system", &["force_system"])
-        .with(systems::physics::CollisionDetectionSystem, "collision_detection_system", &["velocity_system"])
-        .with_system_desc(systems::physics::CollisionProcessingSystemDesc, "collision_processing_system", &["collision_detection_system"])
-        .with_system_desc(systems::BodyCreationSystemDesc, "body_creation_system", &["collision_processing_system"]);
-
-    let mut game = Application::new(assets_dir, MainState::new(), game_data)?;
+        .with(Processor::<scenario::Scenario>::new(), "scenario_processor", &[])
+        .with(systems::InputParsingSystem, "input_parsing_system", &["camera_control_system", "transform_system"])
+        .with(systems::TrajectoryPreviewSystem, "trajectory_preview_system", &["input_parsing_system"])
+        .with(systems::TrailSystem::default(), "trail_system", &["transform_interpolation_system", "body_tint_system"])
+        .with(systems::VectorOverlaySystem::default(), "vector_overlay_system", &["transform_interpolation_system", "camera_control_system"])
+        .with(systems::SelectionSystem::default(), "selection_system", &["camera_control_system", "transform_system"])
+        .with_system_desc(systems::InspectorSystemDesc, "inspector_system", &["selection_system"])
+        .with(systems::BodyEditInputSystem::default(), "body_edit_input_system", &["selection_system"])
+        .with_system_desc(systems::BodyEditSystemDesc, "body_edit_system", &["body_edit_input_system"])
+        .with(systems::SimControlSystem::default(), "sim_control_system", &["input_system"])
+        .with(systems::SnapshotSystem::new(app_root.join("snapshots").join("snapshot.ron")), "snapshot_system", &[]);
+
+    let mut game = Application::build(assets_dir, MainState::new(args.start))?
+        .with_resource(sim_clock)
+        .with_resource(physics_config)
+        .with_resource(trail_config)
+        .build(game_data)?;
     game.run();
 
     Ok(())
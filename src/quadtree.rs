@@ -0,0 +1,191 @@
+use amethyst::core::math::{Point2, Vector2};
+
+// Past this depth bodies share a leaf instead of subdividing further (e.g. bodies spawned on top of each other).
+const MAX_DEPTH: usize = 32;
+
+// Quadtree used by the Barnes-Hut gravity solver.
+// Nodes are stored in a flat Vec, and children are always pushed after their parent,
+// so walking the Vec backwards visits every child before its parent.
+pub struct QuadTree {
+    nodes: Vec<Node>,
+}
+
+struct Node {
+    centre: Point2<f32>,    // Centre of the square this node covers
+    half_width: f32,
+    depth: usize,
+    mass: f32,
+    centre_of_mass: Point2<f32>,
+    children: Option<[usize; 4]>,
+    bodies: Vec<usize>,     // Indices of bodies in this leaf
+}
+
+impl Node {
+    fn new(centre: Point2<f32>, half_width: f32, depth: usize) -> Self {
+        Self {
+            centre,
+            half_width,
+            depth,
+            mass: 0.0,
+            centre_of_mass: centre,
+            children: None,
+            bodies: Vec::with_capacity(1),
+        }
+    }
+
+    // Index into children for the quadrant containing pos
+    fn quadrant(&self, pos: &Point2<f32>) -> usize {
+        let right = (pos.x >= self.centre.x) as usize;
+        let top = (pos.y >= self.centre.y) as usize;
+        right + 2 * top
+    }
+
+    fn contains(&self, pos: &Point2<f32>) -> bool {
+        (pos.x - self.centre.x).abs() <= self.half_width && (pos.y - self.centre.y).abs() <= self.half_width
+    }
+}
+
+impl QuadTree {
+    pub fn new(positions: &[Point2<f32>], masses: &[f32]) -> Self {
+        // Find bounding square of all bodies
+        let mut min = Point2::new(std::f32::INFINITY, std::f32::INFINITY);
+        let mut max = Point2::new(std::f32::NEG_INFINITY, std::f32::NEG_INFINITY);
+        for pos in positions {
+            min.x = min.x.min(pos.x);
+            min.y = min.y.min(pos.y);
+            max.x = max.x.max(pos.x);
+            max.y = max.y.max(pos.y);
+        }
+
+        let mut tree = Self { nodes: Vec::with_capacity(positions.len() * 2) };
+        if positions.is_empty() {
+            return tree;
+        }
+
+        let centre = Point2::from((min.coords + max.coords)/2.0);
+        let half_width = ((max.x - min.x).max(max.y - min.y)/2.0).max(1.0);
+        tree.nodes.push(Node::new(centre, half_width, 0));
+
+        for i in 0..positions.len() {
+            tree.insert(0, i, positions);
+        }
+        tree.compute_mass_distribution(positions, masses);
+
+        tree
+    }
+
+    fn insert(&mut self, mut node_index: usize, body: usize, positions: &[Point2<f32>]) {
+        loop {
+            let node = &self.nodes[node_index];
+
+            if let Some(children) = node.children {
+                node_index = children[node.quadrant(&positions[body])];
+                continue;
+            }
+
+            if node.bodies.is_empty() || node.depth >= MAX_DEPTH {
+                self.nodes[node_index].bodies.push(body);
+                return;
+            }
+
+            // Leaf is occupied, so split it and push the existing bodies down a level
+            self.subdivide(node_index);
+            let existing = std::mem::take(&mut self.nodes[node_index].bodies);
+            for other in existing {
+                let child = {
+                    let node = &self.nodes[node_index];
+                    node.children.unwrap()[node.quadrant(&positions[other])]
+                };
+                self.nodes[child].bodies.push(other);
+            }
+        }
+    }
+
+    fn subdivide(&mut self, node_index: usize) {
+        let (centre, half_width, depth) = {
+            let node = &self.nodes[node_index];
+            (node.centre, node.half_width/2.0, node.depth + 1)
+        };
+
+        let mut children = [0; 4];
+        for (quadrant, child) in children.iter_mut().enumerate() {
+            let dx = if quadrant % 2 == 1 { half_width } else { -half_width };
+            let dy = if quadrant / 2 == 1 { half_width } else { -half_width };
+
+            *child = self.nodes.len();
+            self.nodes.push(Node::new(Point2::new(centre.x + dx, centre.y + dy), half_width, depth));
+        }
+
+        self.nodes[node_index].children = Some(children);
+    }
+
+    // Fills in mass and centre of mass of every node, from the leaves upwards.
+    fn compute_mass_distribution(&mut self, positions: &[Point2<f32>], masses: &[f32]) {
+        for i in (0..self.nodes.len()).rev() {
+            let mut mass = 0.0;
+            let mut r_m_sum: Vector2<f32> = Vector2::zeros();
+
+            match self.nodes[i].children {
+                Some(children) => {
+                    for &child in children.iter() {
+                        let child = &self.nodes[child];
+                        mass += child.mass;
+                        r_m_sum += child.centre_of_mass.coords * child.mass;
+                    }
+                },
+                None => {
+                    for &body in self.nodes[i].bodies.iter() {
+                        mass += masses[body];
+                        r_m_sum += positions[body].coords * masses[body];
+                    }
+                },
+            }
+
+            let node = &mut self.nodes[i];
+            node.mass = mass;
+            if mass > 0.0 {
+                node.centre_of_mass = Point2::from(r_m_sum/mass);
+            }
+        }
+    }
+
+    // Approximate gravitational force on a body from every other body in the tree.
+    // A node is treated as a single point mass when width/distance < theta, so theta = 0 gives the exact sum.
+    // Nodes containing the body itself are always opened, or it would be pulled by its own mass.
+    pub fn force_on(&self, body: usize, positions: &[Point2<f32>], masses: &[f32], theta: f32, softening: f32) -> Vector2<f32> {
+        use crate::systems::physics::gravitational_force;
+
+        let mut force = Vector2::zeros();
+        if self.nodes.is_empty() {
+            return force;
+        }
+
+        let pos = positions[body];
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.mass <= 0.0 {
+                continue;
+            }
+
+            match node.children {
+                None => {
+                    for &other in node.bodies.iter().filter(|&&other| other != body) {
+                        force += gravitational_force(positions[other] - pos, masses[body], masses[other], softening);
+                    }
+                },
+                Some(children) => {
+                    let r_vec = node.centre_of_mass - pos;
+                    if !node.contains(&pos) && node.half_width * 2.0 < theta * r_vec.norm() {
+                        force += gravitational_force(r_vec, masses[body], node.mass, softening);
+                    } else {
+                        stack.extend_from_slice(&children);
+                    }
+                },
+            }
+        }
+
+        force
+    }
+}
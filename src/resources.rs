@@ -1,17 +1,367 @@
 use amethyst::{
+    ecs::Entity,
     renderer::{SpriteRender},
     core::{
         math::{Vector2},
     },
 };
+use serde::{Serialize, Deserialize};
+use crate::integrator::IntegratorKind;
+use crate::components::CollisionResponse;
 
 #[derive(Default, Clone)]
 pub struct SpriteRenders {
     pub planet: Option<SpriteRender>,
+    pub black_hole: Option<SpriteRender>,
+    pub star: Option<SpriteRender>,
+    pub glow: Option<SpriteRender>,
 }
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct MouseInfo {
     pub click_pos: Option<Vector2<f32>>,
     pub is_down: bool,
+    pub spawn_radius: f32,  // Radius of planets added with the mouse
+}
+
+impl Default for MouseInfo {
+    fn default() -> Self {
+        Self {
+            click_pos: None,
+            is_down: false,
+            spawn_radius: 1.0,
+        }
+    }
+}
+
+impl MouseInfo {
+    // Velocity a planet would be thrown at if the mouse was let go at curr_pos, opposite to the drag
+    pub fn launch_velocity(&self, curr_pos: Vector2<f32>) -> Option<Vector2<f32>> {
+        self.click_pos.map(|click_pos| click_pos - curr_pos)
+    }
+}
+
+#[derive(Clone)]
+pub struct CameraInfo {
+    pub zoom: f32,  // World units per screen unit. Bigger is further out.
+    pub drag_pos: Option<(f32, f32)>,   // Screen position of the mouse last frame, while panning with the mouse
+    pub follow: Option<CameraTarget>,
+    pub relative_frame: bool,   // Whether velocities are relative to what's being followed
+    pub frame_velocity: Vector2<f32>,   // Velocity of the frame of reference. Add to get velocities in the world's frame.
+}
+
+impl Default for CameraInfo {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            drag_pos: None,
+            follow: None,
+            relative_frame: false,
+            frame_velocity: Vector2::zeros(),
+        }
+    }
+}
+
+// Body picked with the mouse, shown in the inspector
+#[derive(Default, Clone)]
+pub struct Selection {
+    pub entity: Option<Entity>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CameraTarget {
+    Body(Entity),
+    Barycentre,     // Centre of mass of every body
+}
+
+// Loaded from config/physics.ron
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    pub gravity_solver: GravitySolver,
+    pub integrator: IntegratorKind,
+    pub softening: f32,     // Plummer softening length. Gravity stops growing once bodies are closer than about this.
+    pub continuous_collisions: bool,    // Check the path bodies took over each tick for collisions, not just where they ended up
+    pub collision_response: CollisionResponse,  // For bodies that don't have their own
+    pub fragmentation: Fragmentation,
+    pub tidal_disruption: TidalDisruption,
+    pub timestep: f32,      // Seconds of simulation per physics tick
+    pub max_substeps: u32,  // Most physics ticks that can be run in one frame
+    pub diagnostics_interval: u64,  // Ticks between energy/momentum checks, 0 to turn them off. They are O(n^2).
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            gravity_solver: GravitySolver::DirectSum,
+            integrator: IntegratorKind::VelocityVerlet,
+            softening: 1.0,
+            continuous_collisions: true,
+            collision_response: CollisionResponse::Merge,
+            fragmentation: Fragmentation::default(),
+            tidal_disruption: TidalDisruption::default(),
+            timestep: 1.0/120.0,
+            max_substeps: 8,
+            diagnostics_interval: 30,
+        }
+    }
+}
+
+// When merging bodies are hit hard enough to shatter into debris instead
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fragmentation {
+    pub enabled: bool,
+    pub threshold: f32,     // Impact energy needed, as a multiple of the merged body's binding energy
+    pub max_fragments: usize,
+    pub min_fragment_radius: f32,
+}
+
+impl Default for Fragmentation {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            max_fragments: 8,
+            min_fragment_radius: 1.0,
+        }
+    }
+}
+
+impl Fragmentation {
+    // Pieces a body of this mass breaks into, capped so none are smaller than min_fragment_radius
+    pub fn fragment_num(&self, mass: f32) -> usize {
+        fragment_num(mass, self.min_fragment_radius, self.max_fragments)
+    }
+}
+
+// Bodies inside the Roche limit of a star get torn apart by its tides
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TidalDisruption {
+    pub enabled: bool,
+    pub roche_coefficient: f32,     // 2.44 for a fluid body, 1.26 for a rigid one
+    pub max_fragments: usize,
+    pub min_fragment_radius: f32,   // Bodies that can't be split into at least two of these are left whole
+}
+
+impl Default for TidalDisruption {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            roche_coefficient: 2.44,
+            max_fragments: 5,
+            min_fragment_radius: 1.0,
+        }
+    }
+}
+
+impl TidalDisruption {
+    // Distance from the primary's centre inside which a body is torn apart
+    // d = k R (p_M/p_m)^(1/3), where R is the radius of the primary and p the densities
+    pub fn roche_limit(&self, primary_mass: f32, primary_radius: f32, mass: f32, radius: f32) -> f32 {
+        use crate::tools::volume_of_sphere;
+
+        let primary_density = primary_mass/volume_of_sphere(primary_radius);
+        let density = mass/volume_of_sphere(radius);
+        self.roche_coefficient * primary_radius * (primary_density/density).powf(1.0/3.0)
+    }
+
+    pub fn fragment_num(&self, mass: f32) -> usize {
+        fragment_num(mass, self.min_fragment_radius, self.max_fragments)
+    }
+}
+
+fn fragment_num(mass: f32, min_fragment_radius: f32, max_fragments: usize) -> usize {
+    use crate::entities::body::PLANET_DENSITY;
+
+    let min_fragment_mass = crate::tools::volume_of_sphere(min_fragment_radius) * PLANET_DENSITY;
+    ((mass/min_fragment_mass) as usize).min(max_fragments)
+}
+
+// Loaded from config/trails.ron
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrailConfig {
+    pub enabled: bool,
+    pub length: usize,  // Most points kept per trail
+    pub sample_interval: u64,   // Physics ticks between points
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            length: 120,
+            sample_interval: 4,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum GravitySolver {
+    DirectSum,  // Exact pairwise sum, O(n^2)
+    BarnesHut { theta: f32 },   // Quadtree approximation, O(n log n). theta is the opening angle.
+}
+
+// Keeps physics ticking at a fixed rate, whatever the frame rate.
+// Real time is added each frame, and used up one timestep per physics tick.
+#[derive(Debug, Clone)]
+pub struct SimClock {
+    pub timestep: f32,
+    pub max_substeps: u32,
+    pub accumulator: f32,
+    pub tick: u64,
+    pub elapsed: f64,   // Simulated seconds
+    pub paused: bool,
+    pub time_scale: f32,    // Simulated seconds per real second
+    pub step_requested: bool,   // Run a single tick while paused
+}
+
+// Speeds the simulation can be run at, stepped through with "slow_down" and "speed_up"
+pub const TIME_SCALES: [f32; 10] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0];
+
+impl SimClock {
+    pub fn new(timestep: f32, max_substeps: u32) -> Self {
+        Self {
+            timestep,
+            max_substeps,
+            accumulator: 0.0,
+            tick: 0,
+            elapsed: 0.0,
+            paused: false,
+            time_scale: 1.0,
+            step_requested: false,
+        }
+    }
+
+    // Adds a frame's time, scaled by the time scale, and returns how many physics ticks should be run for it.
+    pub fn ticks_for_frame(&mut self, frame_dt: f32) -> u32 {
+        if self.paused {
+            // The accumulator is left alone, so bodies stay drawn where they were
+            let step = self.step_requested;
+            self.step_requested = false;
+            return if step { 1 } else { 0 };
+        }
+
+        self.accumulator += frame_dt * self.time_scale;
+
+        // Sped up there are more ticks a frame to fit in, so allow proportionally more before dropping time
+        let max_ticks = (self.max_substeps as f32 * self.time_scale.max(1.0)).ceil() as u32;
+        let mut ticks = 0;
+        while self.accumulator >= self.timestep && ticks < max_ticks {
+            self.accumulator -= self.timestep;
+            ticks += 1;
+        }
+
+        // Fell too far behind (e.g. a long hitch), so drop the time rather than trying to catch up
+        if self.accumulator >= self.timestep {
+            warn!("Physics fell behind, dropping {:.3}s of simulation.", self.accumulator - self.accumulator % self.timestep);
+            self.accumulator %= self.timestep;
+        }
+
+        ticks
+    }
+
+    pub fn advance(&mut self) {
+        self.tick += 1;
+        self.elapsed += self.timestep as f64;
+    }
+
+    // How far between the last tick and the next one the current frame is, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        self.accumulator/self.timestep
+    }
+
+    // Moves to the next slower or faster of TIME_SCALES
+    pub fn change_speed(&mut self, faster: bool) {
+        let scale = if faster {
+            TIME_SCALES.iter().find(|&&scale| scale > self.time_scale)
+        } else {
+            TIME_SCALES.iter().rev().find(|&&scale| scale < self.time_scale)
+        };
+
+        if let Some(&scale) = scale {
+            self.time_scale = scale;
+            info!("Simulation speed {}x.", scale);
+        }
+    }
+
+    // Shown on screen
+    pub fn rate_text(&self) -> String {
+        if self.paused {
+            format!("Paused ({}x)", self.time_scale)
+        } else {
+            format!("Speed: {}x", self.time_scale)
+        }
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        let physics_config = PhysicsConfig::default();
+        Self::new(physics_config.timestep, physics_config.max_substeps)
+    }
+}
+
+// Conserved quantities of the whole simulation, and how far they have drifted since they were first measured.
+// Merging collisions lose energy (and the pair's angular momentum about each other), so drift is expected after them.
+#[derive(Debug, Default, Clone)]
+pub struct Diagnostics {
+    pub current: ConservedQuantities,
+    pub initial: Option<ConservedQuantities>,
+    pub tick: u64,  // Tick current was measured at
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ConservedQuantities {
+    pub kinetic_energy: f64,
+    pub potential_energy: f64,
+    pub momentum: Vector2<f64>,
+    pub angular_momentum: f64,  // About the origin. Only has a z component in 2D.
+    pub momentum_scale: f64,    // Sum of m|v|, to measure momentum drift against since total momentum is often ~0
+}
+
+// nalgebra vectors don't implement Default
+impl Default for ConservedQuantities {
+    fn default() -> Self {
+        Self {
+            kinetic_energy: 0.0,
+            potential_energy: 0.0,
+            momentum: Vector2::zeros(),
+            angular_momentum: 0.0,
+            momentum_scale: 0.0,
+        }
+    }
+}
+
+impl ConservedQuantities {
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+}
+
+impl Diagnostics {
+    pub fn energy_drift(&self) -> f64 {
+        self.initial.map_or(0.0, |initial| relative_drift(self.current.total_energy(), initial.total_energy()))
+    }
+
+    pub fn momentum_drift(&self) -> f64 {
+        self.initial.map_or(0.0, |initial| {
+            let change = (self.current.momentum - initial.momentum).norm();
+            if initial.momentum_scale > std::f64::EPSILON { change/initial.momentum_scale } else { change }
+        })
+    }
+
+    pub fn angular_momentum_drift(&self) -> f64 {
+        self.initial.map_or(0.0, |initial| relative_drift(self.current.angular_momentum, initial.angular_momentum))
+    }
+}
+
+fn relative_drift(current: f64, initial: f64) -> f64 {
+    if initial.abs() > std::f64::EPSILON {
+        (current - initial)/initial.abs()
+    } else {
+        current - initial
+    }
 }
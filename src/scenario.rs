@@ -0,0 +1,160 @@
+use amethyst::{
+    prelude::*,
+    assets::{Asset, AssetStorage, Handle, Loader, ProgressCounter, Completion, RonFormat},
+    ecs::VecStorage,
+    core::math::{Point2, Vector2},
+};
+use serde::{Serialize, Deserialize};
+
+use crate::args::Start;
+use crate::components::{BodyType, CollisionResponse};
+use crate::entities::body;
+use crate::events::BodyCreationEvent;
+
+// Initial bodies of a simulation, loaded from assets/scenarios/*.ron
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub bodies: Vec<BodyDescription>,
+    pub ring_systems: Vec<RingSystemDescription>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyDescription {
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub velocity: (f32, f32),
+    pub size: BodySize,
+    #[serde(default)]
+    pub body_type: Option<BodyType>,    // Worked out from mass if not given
+    #[serde(default)]
+    pub collision_response: Option<CollisionResponse>,    // Uses the one in the physics config if not given
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum BodySize {
+    Radius(f32),
+    Mass(f32),
+}
+
+// Parameters of entities::body::add_body_with_rings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RingSystemDescription {
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub velocity: (f32, f32),
+    pub main_body_radius: f32,
+    pub moon_num: usize,
+    pub moon_orbit_radius_range: (f32, f32),    // Starting from surface of body
+    pub moon_body_radius_range: (f32, f32),
+    #[serde(default)]
+    pub orbit_direction_clockwise: bool,
+}
+
+impl Asset for Scenario {
+    const NAME: &'static str = "orbits::Scenario";
+    type Data = Self;
+    type HandleStorage = VecStorage<Handle<Self>>;
+}
+
+impl Scenario {
+    pub fn spawn(&self, world: &mut World) {
+        use body::PLANET_DENSITY;
+
+        let mut rand_thread = rand::thread_rng();
+
+        for description in self.bodies.iter() {
+            let (mass, radius) = match description.size {
+                BodySize::Radius(radius) => (crate::tools::volume_of_sphere(radius) * PLANET_DENSITY, radius),
+                BodySize::Mass(mass) => (mass, body::radius_from_mass(mass)),
+            };
+
+            body::create_body(world, BodyCreationEvent {
+                body_type: description.body_type.unwrap_or_else(|| BodyType::from_mass(mass)),
+                position: Point2::new(description.position.0, description.position.1),
+                velocity: Vector2::new(description.velocity.0, description.velocity.1),
+                mass,
+                radius,
+                collision_response: description.collision_response,
+                tidal_debris: false,
+            });
+        }
+
+        for ring_system in self.ring_systems.iter() {
+            body::add_body_with_rings(
+                world,
+                &mut rand_thread,
+                Point2::new(ring_system.position.0, ring_system.position.1),
+                Vector2::new(ring_system.velocity.0, ring_system.velocity.1),
+                ring_system.main_body_radius,
+                ring_system.moon_num,
+                ring_system.moon_orbit_radius_range,
+                ring_system.moon_body_radius_range,
+                ring_system.orbit_direction_clockwise,
+            );
+        }
+    }
+}
+
+// Loads a scenario through the asset loader, and spawns it once it's ready.
+pub struct ScenarioLoader {
+    path: String,
+    handle: Handle<Scenario>,
+    progress_counter: ProgressCounter,
+}
+
+impl ScenarioLoader {
+    pub fn new(world: &World, path: &str) -> Self {
+        let mut progress_counter = ProgressCounter::default();
+
+        let handle = {
+            let loader = world.read_resource::<Loader>();
+            let scenario_storage = world.read_resource::<AssetStorage<Scenario>>();
+            loader.load(path, RonFormat, &mut progress_counter, &scenario_storage)
+        };
+
+        Self {
+            path: path.to_owned(),
+            handle,
+            progress_counter,
+        }
+    }
+
+    // Spawns the scenario's bodies if it has finished loading.
+    // Returns Complete once spawned, after which this shouldn't be called again.
+    pub fn try_spawn(&self, world: &mut World) -> Completion {
+        match self.progress_counter.complete() {
+            Completion::Complete => {
+                let scenario = world.read_resource::<AssetStorage<Scenario>>().get(&self.handle).cloned();
+
+                match scenario {
+                    Some(scenario) => {
+                        info!("Spawning scenario {}: {} bodies, {} ring systems.", self.path, scenario.bodies.len(), scenario.ring_systems.len());
+                        scenario.spawn(world);
+                        Completion::Complete
+                    },
+                    None => Completion::Loading,    // Imported, but not processed yet
+                }
+            },
+            Completion::Failed => {
+                for error in self.progress_counter.errors() {
+                    error!("Failed to load scenario {}: {}", self.path, error.error);
+                }
+                Completion::Failed
+            },
+            Completion::Loading => Completion::Loading,
+        }
+    }
+}
+
+// Sets up the bodies a run starts with.
+// Returns the scenario loader if a scenario still has to finish loading before it can be spawned.
+pub fn start(world: &mut World, start: &Start) -> Option<ScenarioLoader> {
+    match start {
+        Start::Scenario(path) => Some(ScenarioLoader::new(world, path)),
+        Start::Snapshot(snapshot) => {
+            snapshot.restore_into_world(world);
+            None
+        },
+    }
+}
@@ -0,0 +1,134 @@
+use amethyst::{
+    prelude::*,
+    ecs::{Entities, Join, Write, ReadStorage},
+    core::math::{Point2, Vector2},
+};
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+
+use crate::components::*;
+use crate::events::BodyCreationEvent;
+use crate::resources::{SimClock, Diagnostics};
+use crate::systems::BodyCreationData;
+
+// Everything needed to resume a simulation. Saved and loaded as RON through amethyst's Config trait.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub elapsed: f64,
+    pub bodies: Vec<BodySnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodySnapshot {
+    pub body_type: BodyType,
+    pub position: (f32, f32),
+    pub velocity: (f32, f32),
+    pub mass: f32,
+    pub radius: f32,
+    #[serde(default)]
+    pub collision_response: Option<CollisionResponse>,
+    #[serde(default)]
+    pub tidal_debris: bool,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl Snapshot {
+    // Positions are taken from the last physics tick, like velocities, rather than the interpolated transforms that are drawn.
+    pub fn capture(sim_clock: &SimClock, body_data: &BodyCreationData, interpolations: &ReadStorage<Interpolation>) -> Self {
+        use crate::entities::body::PLANET_DENSITY;
+
+        let bodies = (
+            &body_data.body_type,
+            &body_data.transforms,
+            interpolations.maybe(),
+            &body_data.velocities,
+            &body_data.masses,
+            &body_data.colliders,
+            body_data.collision_responses.maybe(),
+            body_data.tidal_debris.maybe(),
+            body_data.pinned.maybe(),
+        ).join()
+            .map(|(body_type, transform, interpolation, velocity, mass, collider, collision_response, tidal_debris, pinned)| {
+                // Bodies created since the last tick don't have an interpolation yet
+                let position = match interpolation {
+                    Some(interpolation) => interpolation.current,
+                    None => Point2::new(transform.translation().x, transform.translation().y),
+                };
+
+                BodySnapshot {
+                    body_type: *body_type,
+                    position: (position.x, position.y),
+                    velocity: (velocity.x, velocity.y),
+                    mass: mass.0,
+                    radius: collider.radius()
+                        .unwrap_or_else(|| crate::tools::inverse_volume_of_sphere(mass.0/PLANET_DENSITY)),
+                    collision_response: collision_response.copied(),
+                    tidal_debris: tidal_debris.is_some(),
+                    pinned: pinned.is_some(),
+                }
+            }).collect();
+
+        Self {
+            tick: sim_clock.tick,
+            elapsed: sim_clock.elapsed,
+            bodies,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> amethyst::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        self.write(path)?;
+
+        info!("Saved snapshot of {} bodies at tick {} to {:?}.", self.bodies.len(), self.tick, path);
+        Ok(())
+    }
+
+    // Replaces every body in the world with the ones in the snapshot, and winds the clock back to when it was taken.
+    pub fn restore(
+        &self,
+        entities: &mut Entities,
+        body_creation_data: &mut BodyCreationData,
+        sim_clock: &mut SimClock,
+        diagnostics: &mut Diagnostics,
+    ) {
+        for (entity, _) in (&*entities, &body_creation_data.body_type).join() {
+            entities.delete(entity).expect("Could not delete entity.");
+        }
+
+        for body in self.bodies.iter() {
+            let entity = BodyCreationEvent {
+                body_type: body.body_type,
+                position: Point2::new(body.position.0, body.position.1),
+                velocity: Vector2::new(body.velocity.0, body.velocity.1),
+                mass: body.mass,
+                radius: body.radius,
+                collision_response: body.collision_response,
+                tidal_debris: body.tidal_debris,
+            }.build_entity(entities, body_creation_data);
+
+            if body.pinned {
+                body_creation_data.pinned.insert(entity, Pinned).expect("Could not insert pin.");
+            }
+        }
+
+        sim_clock.tick = self.tick;
+        sim_clock.elapsed = self.elapsed;
+        sim_clock.accumulator = 0.0;
+
+        // Drift is measured from the restored bodies, not whatever was there before
+        *diagnostics = Diagnostics::default();
+
+        info!("Restored snapshot of {} bodies at tick {}.", self.bodies.len(), self.tick);
+    }
+
+    // For starting a fresh world from a snapshot
+    pub fn restore_into_world(&self, world: &mut World) {
+        world.exec(|(mut entities, mut body_creation_data, mut sim_clock, mut diagnostics): (Entities<'_>, BodyCreationData<'_>, Write<'_, SimClock>, Write<'_, Diagnostics>)| {
+            self.restore(&mut entities, &mut body_creation_data, &mut sim_clock, &mut diagnostics);
+        });
+    }
+}
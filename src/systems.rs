@@ -1,18 +1,224 @@
 use amethyst::{
-    ecs::{System, SystemData, Write, WriteStorage, ReaderId, Read, Entities, World},
+    ecs::{
+        System, SystemData, Write, WriteStorage, ReadStorage, ReaderId, Read, ReadExpect, Entities, Entity, World, WorldExt, Join,
+        Dispatcher, DispatcherBuilder,
+    },
     core::{
         transform::Transform, 
         SystemDesc,
-        math::{Vector2},
+        Time,
+        math::{Vector2, Vector3, Point2, Point3},
     },
-    renderer::SpriteRender,
+    renderer::{Camera, SpriteRender, palette::Srgba, resources::Tint, transparent::Transparent, debug_drawing::DebugLines},
     shrev::EventChannel,
     shred::ResourceId,
     input::{InputHandler, StringBindings},
+    window::ScreenDimensions,
+    ui::{UiFinder, UiText},
 };
 use crate::components::*;
 use crate::resources::*;
-use crate::events::BodyCreationEvent;
+use crate::events::{BodyCreationEvent, BodyEditEvent, BodyEdit, BodyTypeChanged};
+use crate::snapshot::Snapshot;
+use amethyst::prelude::Config;
+use std::path::PathBuf;
+use std::collections::HashSet;
+
+
+// Physics systems are run in their own dispatchers, so that they can be ticked at a fixed rate
+// independent of the frame rate. See SimClock.
+// A tick is split into three stages, so that bodies can still be created and re-classified while paused.
+pub struct PhysicsDispatcher {
+    step_dispatcher: Dispatcher<'static, 'static>,
+    body_dispatcher: Dispatcher<'static, 'static>,
+    record_dispatcher: Dispatcher<'static, 'static>,
+}
+
+impl PhysicsDispatcher {
+    pub fn new(world: &mut World) -> Self {
+        let collision_processing_system = physics::CollisionProcessingSystemDesc.build(world);
+        let tidal_disruption_system = physics::TidalDisruptionSystemDesc.build(world);
+        let tidal_disruption_processing_system = physics::TidalDisruptionProcessingSystemDesc.build(world);
+        let body_creation_system = BodyCreationSystemDesc.build(world);
+        let body_evolution_system = physics::BodyEvolutionSystemDesc.build(world);
+
+        let mut step_dispatcher = DispatcherBuilder::new()
+            .with(physics::RestoreTransformSystem, "restore_transform_system", &[])
+            .with(physics::IntegrationSystem, "integration_system", &["restore_transform_system"])
+            .with(physics::CollisionDetectionSystem, "collision_detection_system", &["integration_system"])
+            .with(collision_processing_system, "collision_processing_system", &["collision_detection_system"])
+            .with(tidal_disruption_system, "tidal_disruption_system", &["collision_processing_system"])
+            .with(tidal_disruption_processing_system, "tidal_disruption_processing_system", &["tidal_disruption_system"])
+            .build();
+        step_dispatcher.setup(world);
+
+        let mut body_dispatcher = DispatcherBuilder::new()
+            .with(body_creation_system, "body_creation_system", &[])
+            .with(body_evolution_system, "body_evolution_system", &["body_creation_system"])
+            .build();
+        body_dispatcher.setup(world);
+
+        let mut record_dispatcher = DispatcherBuilder::new()
+            .with(physics::StorePositionSystem, "store_position_system", &[])
+            .with(physics::DiagnosticsSystem, "diagnostics_system", &[])
+            .build();
+        record_dispatcher.setup(world);
+
+        Self {
+            step_dispatcher,
+            body_dispatcher,
+            record_dispatcher,
+        }
+    }
+
+    // Runs a single physics tick
+    pub fn tick(&mut self, world: &mut World) {
+        self.step_dispatcher.dispatch(world);
+        self.body_dispatcher.dispatch(world);
+        // Bodies deleted by collisions still show up in joins until now, alongside whatever replaced them
+        world.maintain();
+        self.record_dispatcher.dispatch(world);
+        world.write_resource::<SimClock>().advance();
+    }
+
+    // Creates bodies waiting to be created, and re-classifies bodies whose mass has changed, without moving anything.
+    // For when the simulation is paused, so a scene can still be set up.
+    pub fn update_bodies(&mut self, world: &mut World) {
+        self.body_dispatcher.dispatch(world);
+        world.maintain();
+    }
+}
+
+// Draws bodies between their last two physics positions, so motion is smooth when the frame rate
+// doesn't line up with the physics tick rate.
+pub struct TransformInterpolationSystem;
+
+impl<'a> System<'a> for TransformInterpolationSystem {
+    type SystemData = (
+        Read<'a, SimClock>,
+        ReadStorage<'a, Interpolation>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (sim_clock, interpolations, mut transforms): Self::SystemData) {
+        let alpha = sim_clock.alpha();
+
+        for (interpolation, transform) in (&interpolations, &mut transforms).join() {
+            let pos = interpolation.previous + (interpolation.current - interpolation.previous) * alpha;
+            transform.set_translation_x(pos.x);
+            transform.set_translation_y(pos.y);
+        }
+    }
+}
+
+
+// Colours each body's sprite by its type and mass
+pub struct BodyTintSystem;
+
+impl<'a> System<'a> for BodyTintSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, BodyType>,
+        ReadStorage<'a, Mass>,
+        WriteStorage<'a, Tint>,
+    );
+
+    fn run(&mut self, (entities, body_types, masses, mut tints): Self::SystemData) {
+        for (entity, body_type, mass) in (&entities, &body_types, &masses).join() {
+            let tint = Tint(body_type.tint(mass.0));
+            match tints.get_mut(entity) {
+                Some(existing) => *existing = tint,
+                None => { tints.insert(entity, tint).expect("Could not insert tint."); },
+            }
+        }
+    }
+}
+
+const GLOW_SCALE: f32 = 3.0;    // Size of a glow compared to its star
+const GLOW_ALPHA: f32 = 0.6;
+
+// Gives every star a glow, keeps it on the star, and removes it once the star is gone.
+pub struct GlowSystem;
+
+impl<'a> System<'a> for GlowSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, SpriteRenders>,
+        ReadStorage<'a, BodyType>,
+        WriteStorage<'a, Glow>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, SpriteRender>,
+        WriteStorage<'a, Tint>,
+        WriteStorage<'a, Transparent>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            renders,
+            body_types,
+            mut glows,
+            mut transforms,
+            mut sprite_renders,
+            mut tints,
+            mut transparents,
+        ): Self::SystemData
+    ) {
+        let glow_render = match renders.glow.clone() {
+            Some(glow_render) => glow_render,
+            None => return,
+        };
+
+        let glow_of = |body: Entity, transforms: &WriteStorage<'a, Transform>, tints: &WriteStorage<'a, Tint>| {
+            let body_transform = transforms.get(body)?;
+            let mut transform = Transform::default();
+            transform.set_translation_xyz(body_transform.translation().x, body_transform.translation().y, -0.5);   // Behind bodies
+            transform.set_scale(body_transform.scale() * GLOW_SCALE);
+
+            let mut colour = tints.get(body).map_or(Srgba::new(1.0, 1.0, 1.0, 1.0), |tint| tint.0);
+            colour.alpha = GLOW_ALPHA;
+            Some((transform, Tint(colour)))
+        };
+
+        let mut glowing = HashSet::new();
+        let mut updates = Vec::new();
+
+        for (entity, glow) in (&entities, &glows).join() {
+            match body_types.get(glow.body) {
+                Some(BodyType::Star) => {
+                    glowing.insert(glow.body);
+                    if let Some(update) = glow_of(glow.body, &transforms, &tints) {
+                        updates.push((entity, update));
+                    }
+                },
+                _ => entities.delete(entity).expect("Could not delete entity."),
+            }
+        }
+
+        for (entity, (transform, tint)) in updates {
+            transforms.insert(entity, transform).expect("Could not insert transform.");
+            tints.insert(entity, tint).expect("Could not insert tint.");
+        }
+
+        let new_stars: Vec<Entity> = (&entities, &body_types).join()
+            .filter(|(body, body_type)| matches!(body_type, BodyType::Star) && !glowing.contains(body))
+            .map(|(body, _)| body)
+            .collect();
+
+        for body in new_stars {
+            if let Some((transform, tint)) = glow_of(body, &transforms, &tints) {
+                entities.build_entity()
+                    .with(Glow { body }, &mut glows)
+                    .with(transform, &mut transforms)
+                    .with(glow_render.clone(), &mut sprite_renders)
+                    .with(tint, &mut tints)
+                    .with(Transparent, &mut transparents)
+                    .build();
+            }
+        }
+    }
+}
 
 
 // System for creating bodies from an events channel.
@@ -68,50 +274,124 @@ pub struct BodyCreationData<'a> { // Data needed to create new body
     pub transforms: WriteStorage<'a, Transform>,
     pub velocities: WriteStorage<'a, Velocity>,
     pub colliders: WriteStorage<'a, Collider>,
-    pub forces: WriteStorage<'a, Force>,
     pub masses: WriteStorage<'a, Mass>,
+    pub collision_responses: WriteStorage<'a, CollisionResponse>,
+    pub tidal_debris: WriteStorage<'a, TidalDebris>,
+    pub pinned: WriteStorage<'a, Pinned>,
     pub renders_resource: Read<'a, SpriteRenders>,
     pub render_storage: WriteStorage<'a, SpriteRender>,
 }
 
 
+// Saves a snapshot of the simulation on "save_snapshot", and restores the last one saved on "load_snapshot".
+pub struct SnapshotSystem {
+    path: PathBuf,
+    save_was_down: bool,
+    load_was_down: bool,
+}
+
+impl<'a> System<'a> for SnapshotSystem {
+    type SystemData = (
+        Read<'a, InputHandler<StringBindings>>,
+        Entities<'a>,
+        Write<'a, SimClock>,
+        Write<'a, Diagnostics>,
+        BodyCreationData<'a>,
+        ReadStorage<'a, Interpolation>,
+    );
+
+    fn run(&mut self, (input, mut entities, mut sim_clock, mut diagnostics, mut body_creation_data, interpolations): Self::SystemData) {
+        if just_pressed(&input, "save_snapshot", &mut self.save_was_down) {
+            if let Err(e) = Snapshot::capture(&sim_clock, &body_creation_data, &interpolations).save(&self.path) {
+                error!("Could not save snapshot to {:?}: {}", self.path, e);
+            }
+        }
+
+        if just_pressed(&input, "load_snapshot", &mut self.load_was_down) {
+            match Snapshot::load(&self.path) {
+                Ok(snapshot) => snapshot.restore(&mut entities, &mut body_creation_data, &mut sim_clock, &mut diagnostics),
+                Err(e) => error!("Could not load snapshot from {:?}: {}", self.path, e),
+            }
+        }
+    }
+}
+
+impl SnapshotSystem {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            save_was_down: false,
+            load_was_down: false,
+        }
+    }
+}
+
+// True on the frame an action goes from up to down
+fn just_pressed(input: &InputHandler<StringBindings>, action: &str, was_down: &mut bool) -> bool {
+    let is_down = input.action_is_down(action).unwrap_or(false);
+    let pressed = is_down && !*was_down;
+    *was_down = is_down;
+    pressed
+}
+
+
 pub struct InputParsingSystem;
 
 impl<'a> System<'a> for InputParsingSystem {
     type SystemData = (
         Read<'a, InputHandler<StringBindings>>,
         Write<'a, MouseInfo>,
+        Read<'a, CameraInfo>,
         Write<'a, EventChannel<BodyCreationEvent>>,
+        ReadExpect<'a, ScreenDimensions>,
+        ReadStorage<'a, Camera>,
+        ReadStorage<'a, Transform>,
     );
 
-    fn run(&mut self, (input, mut mouse_info, mut body_creation_channel): Self::SystemData) {
-        use crate::CAMERA_DIMS;
+    fn run(&mut self, (input, mut mouse_info, camera_info, mut body_creation_channel, screen_dimensions, cameras, transforms): Self::SystemData) {
+        let (camera, camera_transform) = match (&cameras, &transforms).join().next() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let screen_to_world = |pos: (f32, f32)| crate::tools::screen_to_world(pos, camera, camera_transform, &screen_dimensions);
+
+        // Scrolling with the modifier held changes the size of planets, instead of zooming
+        if input.action_is_down("planet_size_modifier").unwrap_or(false) {
+            let scroll = input.axis_value("scroll").unwrap_or(0.0);
+            mouse_info.spawn_radius = (mouse_info.spawn_radius + scroll).max(1.0);
+        }
+
+        // Dragging with the modifier held moves the selected body instead
+        let editing = input.action_is_down("edit_modifier").unwrap_or(false);
 
-        if input.action_is_down("add_planet").unwrap_or(false) && !mouse_info.is_down {
-            mouse_info.is_down = true;
-            let pos = input.mouse_position().unwrap();
-            mouse_info.click_pos = Some(Vector2::new(pos.0, CAMERA_DIMS.1 - pos.1));
+        if input.action_is_down("add_planet").unwrap_or(false) && !mouse_info.is_down && !editing {
+            if let Some(pos) = input.mouse_position() {
+                mouse_info.is_down = true;
+                mouse_info.click_pos = Some(screen_to_world(pos));
+            }
         }
 
         // if no longer down
         if !input.action_is_down("add_planet").unwrap_or(false) && mouse_info.is_down && mouse_info.click_pos.is_some() {
             if let Some(curr_pos) = input.mouse_position() {
-                let curr_pos = Vector2::new(curr_pos.0, CAMERA_DIMS.1 - curr_pos.1);
+                let curr_pos = screen_to_world(curr_pos);
 
                 let original_click_pos = mouse_info.click_pos.unwrap();
-                let mouse_spawn_radius = input.axis_value("planet_size").unwrap().max(1.0);
+                let mouse_spawn_radius = mouse_info.spawn_radius;
+                let d_pos = mouse_info.launch_velocity(curr_pos).unwrap();
     
                 mouse_info.is_down = false;
-    
-                let d_pos = original_click_pos - curr_pos;
+
                 let mass = crate::tools::volume_of_sphere(mouse_spawn_radius) * crate::entities::body::PLANET_DENSITY;
     
                 body_creation_channel.single_write(BodyCreationEvent {
                     body_type: BodyType::from_mass(mass),
                     position: original_click_pos.into(),
-                    velocity: d_pos,
+                    velocity: d_pos + camera_info.frame_velocity,     // Thrown relative to the frame of reference
                     mass,
                     radius: mouse_spawn_radius,
+                    collision_response: None,
+                    tidal_debris: false,
                 });
             }
         }
@@ -119,311 +399,2027 @@ impl<'a> System<'a> for InputParsingSystem {
 }
 
 
+// Records where each body has been every few physics ticks, and draws it as a line fading out behind the body.
+#[derive(Default)]
+pub struct TrailSystem {
+    last_sample_tick: u64,
+    toggle_was_down: bool,
+}
 
-pub mod physics {
-    use amethyst::{
-        ecs::{
-            System, SystemData,
-            WriteStorage, ReadStorage, Read, Write, ReaderId,
-            Join,
-            Entities, Entity,
-            World,
-        },
-        core::{
-            SystemDesc,
-            math::{Vector2, Point2},
-            Time,
-            Transform,
-        },
-        shrev::EventChannel,
-    };
-    use std::collections::HashSet;
-    
-    use crate::components::*;
-    use crate::events::*;
-    
-    pub const G: f32 = 0.0001;    // Strength of gravity
-    
-    pub struct VelocitySystem;
-    
-    impl<'a> System<'a> for VelocitySystem {
-        type SystemData = (
-            Read<'a, Time>,
-            WriteStorage<'a, Transform>,
-            ReadStorage<'a, Velocity>,
-        );
-    
-        fn run(&mut self, (time, mut transforms, velocities): Self::SystemData) {
-            let dt= time.delta_seconds();
-    
-            for (transform, velocity) in (&mut transforms, &velocities).join() {
-                transform.prepend_translation_x(velocity.x * dt);
-                transform.prepend_translation_y(velocity.y * dt);
-            }
-        }
-    }
-    
-    pub struct GravitySystem;
-    
-    impl<'a> System<'a> for GravitySystem {
-        type SystemData = (
-            ReadStorage<'a, Transform>,
-            ReadStorage<'a, Mass>,
-            WriteStorage<'a, Force>,
-        );
-    
-        fn run(&mut self, (transforms, masses, mut forces): Self::SystemData) {
-            struct GravBody<'a> {   // Helper struct to make things less confusing
-                force: &'a mut Force,
-                mass: &'a Mass,
-                transform: &'a Transform,
-            }
-
-            let mut grav_bodies: Vec<GravBody> = 
-                (&mut forces, &masses, &transforms).join()
-                    .map(|(force, mass, transform)| GravBody {
-                        force,
-                        mass,
-                        transform,
-                    }).collect();
-
-            // Bodies that experience gravity are collected so that an optimised approach can be used.
-            // Since the force experienced between to planets is equal and _opposite_ for the other planet,
-            // we only need to calculate the force between a pair.
-
-            let len = grav_bodies.len();
-            for i in 0..len-1 { // For every body except from last
-                for j in i+1..len {   // For every body not done (i) onwards
-                    // F = GMm/r^2
-                    // F_vec = (GMm/r^2) r_hat = (GMm/r^3) r_vec
-                    // r is vector from this object to other object
-                    let r_vec_3 = grav_bodies[j].transform.translation() - grav_bodies[i].transform.translation();
-                    let r_vec = Vector2::new(r_vec_3.x, r_vec_3.y); // Convert to 2D
-                    let distance_cubed = r_vec.norm().powi(3);
-                    // grav_force will be experienced by both
-                    let grav_force = (G * grav_bodies[i].mass.0 * grav_bodies[j].mass.0/distance_cubed) * r_vec;
-
-                    grav_bodies[i].force.0 += grav_force;
-                    grav_bodies[j].force.0 -= grav_force;   // -= cause force is applied in opposite direction
+impl<'a> System<'a> for TrailSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, InputHandler<StringBindings>>,
+        Read<'a, SimClock>,
+        Read<'a, Selection>,
+        Write<'a, TrailConfig>,
+        Write<'a, DebugLines>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Tint>,
+        WriteStorage<'a, Trail>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            input,
+            sim_clock,
+            selection,
+            mut trail_config,
+            mut debug_lines,
+            velocities,
+            transforms,
+            tints,
+            mut trails,
+        ): Self::SystemData
+    ) {
+        // With the edit modifier held, only the selected body's trail is toggled
+        if just_pressed(&input, "toggle_trails", &mut self.toggle_was_down) {
+            if input.action_is_down("edit_modifier").unwrap_or(false) {
+                if let Some(trail) = selection.entity.and_then(|entity| trails.get_mut(entity)) {
+                    trail.enabled = !trail.enabled;
                 }
+            } else {
+                trail_config.enabled = !trail_config.enabled;
             }
+        }
 
-            // for (entity, force, mass, transform) in  {
-            //     for (_other_entity, other_mass, other_transform) in
-            //         (&entities, &masses, &transforms).join()
-            //             .filter(|(e, _, _)| e.id() != entity.id()) // If not the same planet
-            //     {
-            //         // F = GMm/r^2
-            //         // F_vec = (GMm/r^2) r_hat = (GMm/r^3) r_vec
-            //         // r is vector from this object to other object
-            //         let r_vec_3 = other_transform.translation() - transform.translation();
-            //         let r_vec = Vector2::new(r_vec_3.x, r_vec_3.y); // Convert to 2D
-            //         let distance_cubed = r_vec.norm().powi(3);
-            //         let grav_force = (G * mass.0 * other_mass.0/distance_cubed) * r_vec;
-    
-            //         force.0 += grav_force;
-            //     }
-            // }
+        // Bodies start recording as soon as they appear
+        let new_bodies: Vec<Entity> = (&entities, &velocities, !&trails).join()
+            .map(|(entity, _, _)| entity)
+            .collect();
+        for entity in new_bodies {
+            trails.insert(entity, Trail::new(true)).expect("Could not insert trail.");
         }
-    }
-    
-    pub struct ForceSystem;
-    
-    impl<'a> System<'a> for ForceSystem {
-        type SystemData = (
-            Read<'a, Time>,
-            ReadStorage<'a, Mass>,
-            WriteStorage<'a, Velocity>,
-            WriteStorage<'a, Force>,
-        );
-    
-        fn run(&mut self, (time, masses, mut velocities, mut forces): Self::SystemData) {
-            let dt = time.delta_seconds();
-            // Update velocities with a force
-            // F = ma, a = F/m, a = dv/dt, dv = a dt
-            for (velocity, force, mass) in (&mut velocities, &forces, &masses).join() {
-                velocity.0 += (force.0/mass.0) * dt;
-            }
-            // For objects with no mass, assume mass is 1
-            for (velocity, force, _) in (&mut velocities, &forces, !&masses).join() {
-                velocity.0 += force.0 * dt;
-            }
-    
-            // Reset all forces to 0
-            for force in (&mut forces).join() {
-                force.x = 0.0;
-                force.y = 0.0;
+
+        // Sampled once per interval of ticks. Compared by interval rather than counting ticks since the last sample,
+        // since loading a snapshot can move the clock either way.
+        let sample_interval = trail_config.sample_interval.max(1);
+        if sim_clock.tick/sample_interval != self.last_sample_tick/sample_interval {
+            self.last_sample_tick = sim_clock.tick;
+
+            for (transform, trail) in (&transforms, &mut trails).join() {
+                let translation = transform.translation();
+                trail.push(Point2::new(translation.x, translation.y), trail_config.length);
             }
         }
-    }
-    
-    pub struct CollisionDetectionSystem;
-    
-    impl<'a> System<'a> for CollisionDetectionSystem {
-        type SystemData = (
-            Entities<'a>,
-            Write<'a, EventChannel<CollisionEvent>>,
-            ReadStorage<'a, Transform>,        // Need write access to all these to add new planets
-            ReadStorage<'a, Collider>,
-        );
-    
-        fn run(
-            &mut self, 
-            (
-                entities,
-                mut collision_event_channel,
-                transforms,
-                colliders,
-            ): Self::SystemData,
-        ) {
-            let mut collision_groups: Vec<HashSet<Entity>> = Vec::new();
-    
-            for (entity, transform, collider) in (&entities, &transforms, &colliders).join() {
-                for (other_entity, other_transform, other_collider) in 
-                    (&entities, &transforms, &colliders).join()
-                        .filter(|(e, _, _)| e.id() != entity.id())    // If the entity is the same as the first entity, don't collide with itself
-                {
-                    if collider.is_colliding_with(transform, other_collider, other_transform) {
-                        Self::put_in_collision_group(&mut collision_groups, entity, other_entity);
-                    }
-                }
-            }
 
-            // Send event to collision channel
-            collision_event_channel.iter_write(
-                collision_groups
-                    .into_iter()
-                    .map(|group| {
-                        CollisionEvent {
-                            group,
-                        }
-                    })
-            );
+        if !trail_config.enabled {
+            return;
         }
-    }
-    
-    impl CollisionDetectionSystem {
-        fn put_in_collision_group(collision_groups: &mut Vec<HashSet<Entity>>, e1: Entity, e2: Entity) {
-            // If either entity is in an existing group, then add the other to that group.
-            // Otherwise, make a new group.
-            let mut existing_group_found = false;
-            let mut already_paired = false; // If they were paired
-    
-            // Check for existing group
-            for group in collision_groups.iter_mut() {
-                let (contains1, contains2) = (group.contains(&e1), group.contains(&e2));
-                if contains1 || contains2 {  // If it contains either one, but not both
-                    if contains1 && contains2 { // Already BOTH in group, so ignore
-                        info!("{:?} and {:?} already paired.", e1, e2);
-                        already_paired = true;
-                    } else {
-                        if contains1 {
-                            info!("Inserting {:?} into group {:?}", e2, group);
-                            if !group.insert(e2) { error!("Entity {} was already in collision group {:?}.", e2.id(), group) }
-                        } else if contains2 {
-                            info!("Inserting {:?} into group {:?}", e1, group);
-                            if !group.insert(e1) { error!("Entity {} was already in collision group {:?}.", e1.id(), group) }
-                        }
-                    }
-                    existing_group_found = true;
-                    break;
-                }
-            }
-    
-            // Make a new group if there was no existing group
-            if !existing_group_found && !already_paired {
-                let mut set = HashSet::with_capacity(2);
-                set.insert(e1);
-                set.insert(e2);
-                collision_groups.push(set);
+
+        for (entity, trail) in (&entities, &trails).join().filter(|(_, trail)| trail.enabled) {
+            let colour = tints.get(entity).map_or(Srgba::new(1.0, 1.0, 1.0, 1.0), |tint| tint.0);
+            let point_num = trail.points.len();
+
+            for (i, (start, end)) in trail.points.iter().zip(trail.points.iter().skip(1)).enumerate() {
+                // Older points are more transparent
+                let alpha = 0.5 * (i + 1) as f32/point_num as f32;
+                debug_lines.draw_line(
+                    Point3::new(start.x, start.y, -0.6),    // Behind bodies and glows
+                    Point3::new(end.x, end.y, -0.6),
+                    Srgba::new(colour.red, colour.green, colour.blue, alpha),
+                );
             }
         }
     }
+}
 
-    pub struct CollisionProcessingSystem {
-        reader_id: ReaderId<CollisionEvent>,
-    }
+const VELOCITY_ARROW_SCALE: f32 = 1.0;      // Same as dragging out a planet, so arrows can be compared with the launch vector
+const ACCELERATION_ARROW_SCALE: f32 = 5.0;
 
-    impl<'a> System<'a> for CollisionProcessingSystem {
-        type SystemData = (
-            Entities<'a>,
-            Read<'a, EventChannel<CollisionEvent>>,
-            Write<'a, EventChannel<BodyCreationEvent>>,
-            ReadStorage<'a, Transform>,
-            ReadStorage<'a, Velocity>,
-            ReadStorage<'a, Mass>,
-        );
+// Toggleable overlay of each body's velocity and its gravitational acceleration from the last physics tick.
+// Velocities are relative to the camera's frame of reference.
+#[derive(Default)]
+pub struct VectorOverlaySystem {
+    enabled: bool,
+    toggle_was_down: bool,
+}
 
-        fn run(
-            &mut self,
-            (
-                entities,
-                collision_event_channel,
-                mut body_creation_event_channel,
-                transforms,
-                velocities,
-                masses,
-            ): Self::SystemData
-        ) {
-            for event in collision_event_channel.read(&mut self.reader_id) {
-                let group: &HashSet<Entity> = &event.group;
+impl<'a> System<'a> for VectorOverlaySystem {
+    type SystemData = (
+        Read<'a, InputHandler<StringBindings>>,
+        Read<'a, CameraInfo>,
+        Write<'a, DebugLines>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Acceleration>,
+    );
 
-                info!("CollisionEvent: {:?}", event);
-        
-                // Find centre of mass = new position
-                // r_com = SUM( m * r ) where r is position vector
-                let mut r_m_sum: Vector2<f32> = Vector2::zeros();
-                let mut mass_sum: f32 = 0.0;
-    
-                // Momentum before = momentum after
-                let mut momentum_sum: Vector2<f32> = Vector2::zeros();
+    fn run(&mut self, (input, camera_info, mut debug_lines, transforms, velocities, accelerations): Self::SystemData) {
+        if just_pressed(&input, "toggle_vectors", &mut self.toggle_was_down) {
+            self.enabled = !self.enabled;
+        }
+        if !self.enabled {
+            return;
+        }
 
-                for entity in group {
-                    // Get mass. Assume mass is 1 if no mass.
-                    let mass = masses.get(*entity)
-                        .unwrap_or(&Mass(1.0));
-                    let velocity: Velocity = velocities.get(*entity).copied()
-                        .unwrap_or(Velocity(Vector2::zeros()));
-    
-                    let translation = transforms.get(*entity).unwrap().translation();
-                    let r = Vector2::new(translation.x, translation.y);
-    
-                    r_m_sum += r * mass.0;
-                    mass_sum += mass.0;
-                    momentum_sum += velocity.0 * mass.0;
-    
-                    entities.delete(*entity).expect("Could not delete entity.");
-                }
-    
-                // p = mv, v = p/m
-                let r_com: Point2<f32> = Point2::from(r_m_sum/mass_sum as f32);
-                let vel = momentum_sum/mass_sum;
-
-                body_creation_event_channel.single_write(BodyCreationEvent {
-                    body_type: BodyType::from_mass(mass_sum),
-                    position: r_com,
-                    velocity: vel,
-                    mass: mass_sum,
-                    radius: crate::tools::inverse_volume_of_sphere(mass_sum/crate::entities::body::PLANET_DENSITY),
-                });
+        for (transform, velocity, acceleration) in (&transforms, &velocities, accelerations.maybe()).join() {
+            let pos = Vector2::new(transform.translation().x, transform.translation().y);
+
+            draw_arrow(&mut debug_lines, pos, (velocity.0 - camera_info.frame_velocity) * VELOCITY_ARROW_SCALE, Srgba::new(0.3, 1.0, 0.4, 0.9));
+            if let Some(acceleration) = acceleration {
+                draw_arrow(&mut debug_lines, pos, acceleration.0 * ACCELERATION_ARROW_SCALE, Srgba::new(1.0, 0.4, 0.3, 0.9));
             }
         }
     }
+}
 
-    impl<'a> CollisionProcessingSystem {
-        pub fn new(reader_id: ReaderId<CollisionEvent>) -> Self {
-            Self { reader_id: reader_id }
-        }
+fn draw_arrow(debug_lines: &mut DebugLines, start: Vector2<f32>, vector: Vector2<f32>, colour: Srgba) {
+    const HEAD_FRACTION: f32 = 0.2;     // Length of the head compared to the arrow, up to MAX_HEAD_LENGTH
+    const MAX_HEAD_LENGTH: f32 = 8.0;
+
+    let length = vector.norm();
+    if length <= std::f32::EPSILON {
+        return;
     }
 
-    pub struct CollisionProcessingSystemDesc;
+    let end = start + vector;
+    let point = |pos: Vector2<f32>| Point3::new(pos.x, pos.y, 0.5);
+    debug_lines.draw_line(point(start), point(end), colour);
 
-    impl<'a, 'b> SystemDesc<'a, 'b, CollisionProcessingSystem> for CollisionProcessingSystemDesc {
-        fn build(self, world: &mut World) -> CollisionProcessingSystem {
-            <CollisionProcessingSystem as System<'_>>::SystemData::setup(world);
-        
-            let reader_id = world.fetch_mut::<EventChannel<CollisionEvent>>().register_reader();
-            CollisionProcessingSystem::new(reader_id)
+    // Two sides of the head, 30 degrees either side of the shaft
+    let back = -vector/length * (length * HEAD_FRACTION).min(MAX_HEAD_LENGTH);
+    let (sin, cos) = (std::f32::consts::PI/6.0).sin_cos();
+    for side in [-1.0, 1.0].iter() {
+        let head = Vector2::new(back.x * cos - back.y * sin * side, back.x * sin * side + back.y * cos);
+        debug_lines.draw_line(point(end), point(end + head), colour);
+    }
+}
+
+const SELECTION_PICK_RADIUS: f32 = 6.0;     // Pixels outside a body's collider that still count as clicking on it
+
+// Selects the body under the mouse on "select", and draws a highlight around it
+#[derive(Default)]
+pub struct SelectionSystem {
+    select_was_down: bool,
+}
+
+impl<'a> System<'a> for SelectionSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, InputHandler<StringBindings>>,
+        Write<'a, Selection>,
+        Write<'a, DebugLines>,
+        ReadExpect<'a, ScreenDimensions>,
+        ReadStorage<'a, Camera>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Collider>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            input,
+            mut selection,
+            mut debug_lines,
+            screen_dimensions,
+            cameras,
+            transforms,
+            colliders,
+        ): Self::SystemData
+    ) {
+        // Forget bodies that have gone, e.g. merged into something else
+        if let Some(entity) = selection.entity {
+            if !entities.is_alive(entity) || colliders.get(entity).is_none() {
+                selection.entity = None;
+            }
+        }
+
+        if just_pressed(&input, "select", &mut self.select_was_down) {
+            let (camera, camera_transform) = match (&cameras, &transforms).join().next() {
+                Some(camera) => camera,
+                None => return,
+            };
+
+            if let Some(pos) = input.mouse_position() {
+                let screen_to_world = |pos: (f32, f32)| crate::tools::screen_to_world(pos, camera, camera_transform, &screen_dimensions);
+                let mouse_pos = Point2::from(screen_to_world(pos));
+                let pick_radius = (screen_to_world((pos.0 + SELECTION_PICK_RADIUS, pos.1)) - mouse_pos.coords).norm();
+
+                // Closest collider to the mouse. Clicking on nothing clears the selection.
+                selection.entity = (&entities, &transforms, &colliders).join()
+                    .filter_map(|(entity, transform, collider)| {
+                        collider.distance_to_point(transform, &mouse_pos).map(|distance| (entity, distance))
+                    })
+                    .filter(|(_, distance)| *distance <= pick_radius)
+                    .min_by(|(_, d1), (_, d2)| d1.partial_cmp(d2).unwrap_or(std::cmp::Ordering::Equal))
+                    .map(|(entity, _)| entity);
+            }
+        }
+
+        if let Some(entity) = selection.entity {
+            if let (Some(transform), Some(radius)) = (transforms.get(entity), colliders.get(entity).and_then(|collider| collider.radius())) {
+                let centre = Point3::new(transform.translation().x, transform.translation().y, 0.5);
+                debug_lines.draw_circle(centre, radius * 1.3 + 2.0, 32, Srgba::new(1.0, 1.0, 0.3, 1.0));
+            }
+        }
+    }
+}
+
+// Shows the selected body's properties in the "inspector" label from ui/inspector.ron,
+// and the last time it changed type.
+pub struct InspectorSystem {
+    label: Option<Entity>,
+    reader_id: ReaderId<BodyTypeChanged>,
+    last_type_change: Option<BodyTypeChanged>,
+}
+
+impl<'a> System<'a> for InspectorSystem {
+    type SystemData = (
+        Entities<'a>,
+        UiFinder<'a>,
+        Read<'a, Selection>,
+        Read<'a, EventChannel<BodyTypeChanged>>,
+        WriteStorage<'a, UiText>,
+        ReadStorage<'a, BodyType>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Mass>,
+        ReadStorage<'a, Collider>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            finder,
+            selection,
+            body_type_changed_channel,
+            mut ui_text,
+            body_types,
+            transforms,
+            velocities,
+            masses,
+            colliders,
+        ): Self::SystemData
+    ) {
+        // Only changes made while a body is selected are kept, and forgotten once something else is
+        for event in body_type_changed_channel.read(&mut self.reader_id) {
+            if Some(event.body) == selection.entity {
+                self.last_type_change = Some(*event);
+            }
+        }
+        self.last_type_change = self.last_type_change.filter(|change| Some(change.body) == selection.entity);
+
+        if self.label.is_none() {
+            self.label = finder.find("inspector");
+        }
+        let label = match self.label.and_then(|label| ui_text.get_mut(label)) {
+            Some(label) => label,
+            None => return,
+        };
+
+        let entity = match selection.entity {
+            Some(entity) => entity,
+            None => {
+                label.text.clear();
+                return;
+            },
+        };
+
+        let body = (body_types.get(entity), transforms.get(entity), velocities.get(entity), masses.get(entity));
+        let (body_type, transform, velocity, mass) = match body {
+            (Some(body_type), Some(transform), Some(velocity), Some(mass)) => (body_type, transform, velocity, mass),
+            _ => {
+                label.text.clear();
+                return;
+            },
+        };
+        let radius = colliders.get(entity).and_then(|collider| collider.radius());
+        let position = Vector2::new(transform.translation().x, transform.translation().y);
+
+        // Body with the most negative orbital energy relative to this one
+        // e = v^2/2 - G(M + m)/r
+        let bound_to = (&entities, &transforms, &velocities, &masses).join()
+            .filter(|(other, _, _, _)| *other != entity)
+            .map(|(other, other_transform, other_velocity, other_mass)| {
+                let r = (Vector2::new(other_transform.translation().x, other_transform.translation().y) - position).norm();
+                let v = other_velocity.0 - velocity.0;
+                (other, v.norm_squared()/2.0 - crate::systems::physics::G * (mass.0 + other_mass.0)/r)
+            })
+            .filter(|(_, energy)| *energy < 0.0)
+            .min_by(|(_, e1), (_, e2)| e1.partial_cmp(e2).unwrap_or(std::cmp::Ordering::Equal));
+
+        label.text = format!(
+            "{:?} {:?}\nMass: {:.3e}\nRadius: {}\nVelocity: ({:.2}, {:.2})\nSpeed: {:.2}\nBound to: {}{}",
+            body_type,
+            entity,
+            mass.0,
+            radius.map_or("-".to_string(), |radius| format!("{:.2}", radius)),
+            velocity.x,
+            velocity.y,
+            velocity.norm(),
+            bound_to.map_or("nothing".to_string(), |(other, _)| match body_types.get(other) {
+                Some(other_type) => format!("{:?} {:?}", other_type, other),
+                None => format!("{:?}", other),
+            }),
+            self.last_type_change.map_or(String::new(), |change| format!("\nChanged from {:?} to {:?}", change.from, change.to)),
+        );
+    }
+}
+
+pub struct InspectorSystemDesc;
+
+impl<'a, 'b> SystemDesc<'a, 'b, InspectorSystem> for InspectorSystemDesc {
+    fn build(self, world: &mut World) -> InspectorSystem {
+        <InspectorSystem as System<'_>>::SystemData::setup(world);
+
+        let reader_id = world.fetch_mut::<EventChannel<BodyTypeChanged>>().register_reader();
+        InspectorSystem {
+            label: None,
+            reader_id,
+            last_type_change: None,
+        }
+    }
+}
+
+const RESIZE_STEP: f32 = 1.1;   // Radius multiplier per notch of the scroll wheel
+
+// Turns input into edits of the selected body:
+// drag with the modifier to move it, scroll with the modifier to resize it, hold "aim_velocity" to point its
+// velocity at the mouse, "pin" to hold it in place, and "delete" to remove it.
+#[derive(Default)]
+pub struct BodyEditInputSystem {
+    pin_was_down: bool,
+    delete_was_down: bool,
+}
+
+impl<'a> System<'a> for BodyEditInputSystem {
+    type SystemData = (
+        Read<'a, InputHandler<StringBindings>>,
+        Read<'a, Selection>,
+        Read<'a, CameraInfo>,
+        ReadExpect<'a, ScreenDimensions>,
+        Write<'a, EventChannel<BodyEditEvent>>,
+        ReadStorage<'a, Camera>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            input,
+            selection,
+            camera_info,
+            screen_dimensions,
+            mut body_edit_channel,
+            cameras,
+            transforms,
+        ): Self::SystemData
+    ) {
+        // Checked before anything else, so presses aren't remembered from when nothing was selected
+        let pin_pressed = just_pressed(&input, "pin", &mut self.pin_was_down);
+        let delete_pressed = just_pressed(&input, "delete", &mut self.delete_was_down);
+
+        let body = match selection.entity {
+            Some(body) => body,
+            None => return,
+        };
+        let (camera, camera_transform) = match (&cameras, &transforms).join().next() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let mouse_pos = input.mouse_position()
+            .map(|pos| crate::tools::screen_to_world(pos, camera, camera_transform, &screen_dimensions));
+        let editing = input.action_is_down("edit_modifier").unwrap_or(false);
+
+        let mut edits = Vec::new();
+
+        if let Some(mouse_pos) = mouse_pos {
+            if editing && input.action_is_down("add_planet").unwrap_or(false) {
+                edits.push(BodyEdit::Move(Point2::from(mouse_pos)));
+            }
+
+            if input.action_is_down("aim_velocity").unwrap_or(false) {
+                if let Some(transform) = transforms.get(body) {
+                    let position = Vector2::new(transform.translation().x, transform.translation().y);
+                    edits.push(BodyEdit::SetVelocity(mouse_pos - position + camera_info.frame_velocity));
+                }
+            }
+        }
+
+        let scroll = input.axis_value("scroll").unwrap_or(0.0);
+        if editing && scroll != 0.0 {
+            edits.push(BodyEdit::Resize(RESIZE_STEP.powf(scroll)));
+        }
+        if pin_pressed {
+            edits.push(BodyEdit::TogglePin);
+        }
+        if delete_pressed {
+            edits.push(BodyEdit::Delete);
+        }
+
+        body_edit_channel.iter_write(edits.into_iter().map(|edit| BodyEditEvent { body, edit }));
+    }
+}
+
+// Applies edits made to bodies by hand.
+// Anything that changes the gravity field clears the accelerations cached by the integrator, so they get recalculated.
+pub struct BodyEditSystem {
+    reader_id: ReaderId<BodyEditEvent>,
+}
+
+impl<'a> System<'a> for BodyEditSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, EventChannel<BodyEditEvent>>,
+        ReadStorage<'a, BodyType>,
+        WriteStorage<'a, Transform>,
+        WriteStorage<'a, Interpolation>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, Acceleration>,
+        WriteStorage<'a, Mass>,
+        WriteStorage<'a, Collider>,
+        WriteStorage<'a, Pinned>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            body_edit_channel,
+            body_types,
+            mut transforms,
+            mut interpolations,
+            mut velocities,
+            mut accelerations,
+            mut masses,
+            mut colliders,
+            mut pinned,
+        ): Self::SystemData
+    ) {
+        use crate::entities::body::PLANET_SPRITE_RATIO;
+        use ncollide2d::shape::Ball;
+
+        for event in body_edit_channel.read(&mut self.reader_id) {
+            let body = event.body;
+            if !entities.is_alive(body) {
+                continue;
+            }
+
+            match event.edit {
+                BodyEdit::Move(position) => {
+                    if let Some(transform) = transforms.get_mut(body) {
+                        transform.set_translation_x(position.x);
+                        transform.set_translation_y(position.y);
+                    }
+                    // Or it would be put back where it was at the start of the next physics tick
+                    if let Some(interpolation) = interpolations.get_mut(body) {
+                        *interpolation = Interpolation::at(position);
+                    }
+                    accelerations.clear();
+                },
+                BodyEdit::Resize(factor) => {
+                    let (mass, radius) = match (masses.get_mut(body), colliders.get(body).and_then(|collider| collider.radius())) {
+                        (Some(mass), Some(radius)) => {
+                            mass.0 *= factor.powi(3);
+                            let radius = match body_types.get(body) {
+                                Some(BodyType::BlackHole) => crate::tools::schwarzschild_radius(mass.0),
+                                _ => radius * factor,
+                            };
+                            (mass.0, radius)
+                        },
+                        _ => continue,
+                    };
+                    info!("Resized {:?} to radius {:.2}, mass {:.3e}.", body, radius, mass);
+
+                    colliders.insert(body, Collider(Box::new(Ball::new(radius)))).expect("Could not insert collider.");
+                    if let Some(transform) = transforms.get_mut(body) {
+                        let scale = PLANET_SPRITE_RATIO * radius;
+                        transform.set_scale(Vector3::new(scale, scale, 1.0));
+                    }
+                    accelerations.clear();
+                },
+                BodyEdit::SetVelocity(new_velocity) => {
+                    if let Some(velocity) = velocities.get_mut(body) {
+                        velocity.0 = new_velocity;
+                    }
+                },
+                BodyEdit::TogglePin => {
+                    if pinned.contains(body) {
+                        pinned.remove(body);
+                    } else {
+                        pinned.insert(body, Pinned).expect("Could not insert pin.");
+                        if let Some(velocity) = velocities.get_mut(body) {
+                            velocity.0 = Vector2::zeros();
+                        }
+                    }
+                },
+                BodyEdit::Delete => {
+                    entities.delete(body).expect("Could not delete entity.");
+                    accelerations.clear();
+                },
+            }
+        }
+    }
+}
+
+pub struct BodyEditSystemDesc;
+
+impl<'a, 'b> SystemDesc<'a, 'b, BodyEditSystem> for BodyEditSystemDesc {
+    fn build(self, world: &mut World) -> BodyEditSystem {
+        <BodyEditSystem as System<'_>>::SystemData::setup(world);
+
+        let reader_id = world.fetch_mut::<EventChannel<BodyEditEvent>>().register_reader();
+        BodyEditSystem { reader_id }
+    }
+}
+
+// Pauses, single-steps and changes the speed of the simulation, through the SimClock.
+// Stepping while running pauses first.
+#[derive(Default)]
+pub struct SimControlSystem {
+    pause_was_down: bool,
+    step_was_down: bool,
+    slow_down_was_down: bool,
+    speed_up_was_down: bool,
+}
+
+impl<'a> System<'a> for SimControlSystem {
+    type SystemData = (
+        Read<'a, InputHandler<StringBindings>>,
+        Write<'a, SimClock>,
+    );
+
+    fn run(&mut self, (input, mut sim_clock): Self::SystemData) {
+        if just_pressed(&input, "pause", &mut self.pause_was_down) {
+            sim_clock.paused = !sim_clock.paused;
+            info!("Simulation {}.", if sim_clock.paused { "paused" } else { "resumed" });
+        }
+
+        if just_pressed(&input, "step", &mut self.step_was_down) {
+            sim_clock.paused = true;
+            sim_clock.step_requested = true;
+        }
+
+        if just_pressed(&input, "slow_down", &mut self.slow_down_was_down) {
+            sim_clock.change_speed(false);
+        }
+        if just_pressed(&input, "speed_up", &mut self.speed_up_was_down) {
+            sim_clock.change_speed(true);
+        }
+    }
+}
+
+const PREVIEW_TIME: f32 = 5.0;      // Seconds of motion to predict
+const PREVIEW_TIMESTEP: f32 = 1.0/60.0;
+
+// While dragging to add a planet, draws the launch vector and the path the planet would take.
+// The path is predicted with every other body held still, which is close enough for a few seconds ahead.
+pub struct TrajectoryPreviewSystem;
+
+impl<'a> System<'a> for TrajectoryPreviewSystem {
+    type SystemData = (
+        Read<'a, InputHandler<StringBindings>>,
+        Read<'a, MouseInfo>,
+        Read<'a, CameraInfo>,
+        Read<'a, PhysicsConfig>,
+        ReadExpect<'a, ScreenDimensions>,
+        Write<'a, DebugLines>,
+        ReadStorage<'a, Camera>,
+        ReadStorage<'a, Transform>,
+        ReadStorage<'a, Mass>,
+        ReadStorage<'a, Collider>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            input,
+            mouse_info,
+            camera_info,
+            physics_config,
+            screen_dimensions,
+            mut debug_lines,
+            cameras,
+            transforms,
+            masses,
+            colliders,
+        ): Self::SystemData
+    ) {
+        use crate::systems::physics::gravitational_force;
+
+        let click_pos = match mouse_info.click_pos {
+            Some(click_pos) if mouse_info.is_down => click_pos,
+            _ => return,
+        };
+        let (camera, camera_transform) = match (&cameras, &transforms).join().next() {
+            Some(camera) => camera,
+            None => return,
+        };
+        let curr_pos = match input.mouse_position() {
+            Some(pos) => crate::tools::screen_to_world(pos, camera, camera_transform, &screen_dimensions),
+            None => return,
+        };
+        let launch_velocity = mouse_info.launch_velocity(curr_pos).unwrap();
+
+        let point = |pos: Vector2<f32>| Point3::new(pos.x, pos.y, 0.5);   // In front of bodies
+
+        // Planet to be added, and which way it will be thrown
+        debug_lines.draw_circle(point(click_pos), mouse_info.spawn_radius, 24, Srgba::new(1.0, 1.0, 1.0, 0.8));
+        debug_lines.draw_line(point(click_pos), point(click_pos + launch_velocity), Srgba::new(0.4, 0.8, 1.0, 1.0));
+
+        let bodies: Vec<(Vector2<f32>, f32, f32)> = (&transforms, &masses, colliders.maybe()).join()
+            .map(|(transform, mass, collider)| (
+                Vector2::new(transform.translation().x, transform.translation().y),
+                mass.0,
+                collider.and_then(|collider| collider.radius()).unwrap_or(0.0),
+            ))
+            .collect();
+
+        let mut pos = click_pos;
+        let mut vel = launch_velocity + camera_info.frame_velocity;
+        let steps = (PREVIEW_TIME/PREVIEW_TIMESTEP) as usize;
+
+        for step in 0..steps {
+            let acceleration: Vector2<f32> = bodies.iter()
+                .map(|(body_pos, mass, _)| gravitational_force(body_pos - pos, 1.0, *mass, physics_config.softening))
+                .sum();
+
+            let last_pos = pos;
+            vel += acceleration * PREVIEW_TIMESTEP;
+            pos += vel * PREVIEW_TIMESTEP;
+
+            // Fades out further into the future
+            let alpha = 1.0 - step as f32/steps as f32;
+            debug_lines.draw_line(point(last_pos), point(pos), Srgba::new(1.0, 1.0, 1.0, alpha * 0.6));
+
+            let hit = bodies.iter()
+                .any(|(body_pos, _, radius)| (body_pos - pos).norm() < radius + mouse_info.spawn_radius);
+            if hit {
+                debug_lines.draw_circle(point(pos), mouse_info.spawn_radius, 16, Srgba::new(1.0, 0.3, 0.2, alpha));
+                break;
+            }
+        }
+    }
+}
+
+const CAMERA_PAN_SPEED: f32 = 800.0;    // World units per second, at a zoom of 1
+const CAMERA_ZOOM_STEP: f32 = 1.1;      // Zoom per notch of the scroll wheel
+const CAMERA_ZOOM_RANGE: (f32, f32) = (0.05, 50.0);
+const CAMERA_FOLLOW_RATE: f32 = 5.0;    // How quickly the camera catches up with what it's following. Higher is stiffer.
+const CAMERA_FOLLOW_PICK_RADIUS: f32 = 50.0;   // How close to a body, in pixels, the mouse has to be to follow it
+
+// Pans the camera with WASD or by dragging with the middle mouse button, and zooms with the scroll wheel.
+// Zoom is the scale of the camera's transform, so it is taken into account anywhere the camera transform is.
+// Can also follow the selected body (or the one under the mouse) or the barycentre, and optionally make that the frame of reference.
+#[derive(Default)]
+pub struct CameraControlSystem {
+    follow_body_was_down: bool,
+    follow_barycentre_was_down: bool,
+    relative_frame_was_down: bool,
+}
+
+impl<'a> System<'a> for CameraControlSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, InputHandler<StringBindings>>,
+        Read<'a, Time>,
+        Write<'a, CameraInfo>,
+        Read<'a, Selection>,
+        ReadExpect<'a, ScreenDimensions>,
+        ReadStorage<'a, Camera>,
+        WriteStorage<'a, Transform>,
+        ReadStorage<'a, Mass>,
+        ReadStorage<'a, Velocity>,
+    );
+
+    fn run(&mut self, (entities, input, time, mut camera_info, selection, screen_dimensions, cameras, mut transforms, masses, velocities): Self::SystemData) {
+        let (camera, camera_transform) = match (&cameras, &transforms).join().next() {
+            Some((camera, camera_transform)) => (camera.clone(), camera_transform.clone()),
+            None => return,
+        };
+        let screen_to_world = |pos: (f32, f32)| crate::tools::screen_to_world(pos, &camera, &camera_transform, &screen_dimensions);
+
+        if just_pressed(&input, "follow_body", &mut self.follow_body_was_down) {
+            camera_info.follow = match camera_info.follow {
+                Some(CameraTarget::Body(_)) => None,
+                _ if selection.entity.is_some() => selection.entity.map(CameraTarget::Body),
+                _ => input.mouse_position()
+                    .and_then(|pos| {
+                        let mouse_pos = screen_to_world(pos);
+                        let pick_radius = (screen_to_world((pos.0 + CAMERA_FOLLOW_PICK_RADIUS, pos.1)) - mouse_pos).norm();
+
+                        // Closest body to the mouse, if it's close enough
+                        (&entities, &transforms, &masses).join()
+                            .map(|(entity, transform, _)| (entity, (Vector2::new(transform.translation().x, transform.translation().y) - mouse_pos).norm()))
+                            .filter(|(_, distance)| *distance < pick_radius)
+                            .min_by(|(_, d1), (_, d2)| d1.partial_cmp(d2).unwrap_or(std::cmp::Ordering::Equal))
+                            .map(|(entity, _)| CameraTarget::Body(entity))
+                    }),
+            };
+            info!("Camera following {:?}", camera_info.follow);
+        }
+        if just_pressed(&input, "follow_barycentre", &mut self.follow_barycentre_was_down) {
+            camera_info.follow = match camera_info.follow {
+                Some(CameraTarget::Barycentre) => None,
+                _ => Some(CameraTarget::Barycentre),
+            };
+            info!("Camera following {:?}", camera_info.follow);
+        }
+        if just_pressed(&input, "toggle_relative_frame", &mut self.relative_frame_was_down) {
+            camera_info.relative_frame = !camera_info.relative_frame;
+        }
+
+        // Position and velocity of what's being followed
+        let target = match camera_info.follow {
+            Some(CameraTarget::Body(entity)) => transforms.get(entity).map(|transform| (
+                Vector2::new(transform.translation().x, transform.translation().y),
+                velocities.get(entity).map_or(Vector2::zeros(), |velocity| velocity.0),
+            )),
+            Some(CameraTarget::Barycentre) => {
+                let mut r_m_sum: Vector2<f32> = Vector2::zeros();
+                let mut momentum_sum: Vector2<f32> = Vector2::zeros();
+                let mut mass_sum: f32 = 0.0;
+
+                for (transform, mass, velocity) in (&transforms, &masses, velocities.maybe()).join() {
+                    r_m_sum += Vector2::new(transform.translation().x, transform.translation().y) * mass.0;
+                    momentum_sum += velocity.map_or(Vector2::zeros(), |velocity| velocity.0) * mass.0;
+                    mass_sum += mass.0;
+                }
+
+                if mass_sum > 0.0 {
+                    Some((r_m_sum/mass_sum, momentum_sum/mass_sum))
+                } else {
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if target.is_none() && camera_info.follow.is_some() {
+            info!("Camera lost {:?}, no longer following.", camera_info.follow);   // e.g. it merged into something
+            camera_info.follow = None;
+        }
+        camera_info.frame_velocity = match target {
+            Some((_, velocity)) if camera_info.relative_frame => velocity,
+            _ => Vector2::zeros(),
+        };
+
+        // Scroll is used for resizing instead while either modifier is held
+        let modifier_down = input.action_is_down("planet_size_modifier").unwrap_or(false)
+            || input.action_is_down("edit_modifier").unwrap_or(false);
+        if !modifier_down {
+            let scroll = input.axis_value("scroll").unwrap_or(0.0);
+            if scroll != 0.0 {
+                camera_info.zoom = (camera_info.zoom * CAMERA_ZOOM_STEP.powf(-scroll))
+                    .max(CAMERA_ZOOM_RANGE.0).min(CAMERA_ZOOM_RANGE.1);
+            }
+        }
+
+        // World units to move by
+        let mut pan = Vector2::new(
+            input.axis_value("camera_x").unwrap_or(0.0),
+            input.axis_value("camera_y").unwrap_or(0.0),
+        ) * CAMERA_PAN_SPEED * camera_info.zoom * time.delta_real_seconds();
+
+        if input.action_is_down("pan_camera").unwrap_or(false) {
+            if let Some(pos) = input.mouse_position() {
+                if let Some(last_pos) = camera_info.drag_pos {
+                    // World moves with the mouse, so the camera moves the other way
+                    pan += screen_to_world(last_pos) - screen_to_world(pos);
+                }
+                camera_info.drag_pos = Some(pos);
+            }
+        } else {
+            camera_info.drag_pos = None;
+        }
+
+        // Moving the camera by hand stops it following
+        if pan != Vector2::zeros() && camera_info.follow.is_some() {
+            camera_info.follow = None;
+            camera_info.frame_velocity = Vector2::zeros();
+        }
+
+        // Fraction of the way to the target to move this frame, independent of frame rate
+        let follow_fraction = 1.0 - (-CAMERA_FOLLOW_RATE * time.delta_real_seconds()).exp();
+
+        for (_, transform) in (&cameras, &mut transforms).join() {
+            match target {
+                Some((target_pos, _)) if camera_info.follow.is_some() => {
+                    let camera_pos = Vector2::new(transform.translation().x, transform.translation().y);
+                    let step = (target_pos - camera_pos) * follow_fraction;
+                    transform.prepend_translation_x(step.x);
+                    transform.prepend_translation_y(step.y);
+                },
+                _ => {
+                    transform.prepend_translation_x(pan.x);
+                    transform.prepend_translation_y(pan.y);
+                },
+            }
+            transform.set_scale(Vector3::new(camera_info.zoom, camera_info.zoom, 1.0));
+        }
+    }
+}
+
+
+pub mod physics {
+    use amethyst::{
+        ecs::{
+            System, SystemData,
+            WriteStorage, ReadStorage, Read, Write, ReaderId,
+            Join, BitSet, storage::ComponentEvent,
+            Entities, Entity,
+            World,
+        },
+        core::{
+            SystemDesc,
+            math::{Vector2, Vector3, Point2, Isometry2},
+            Transform,
+        },
+        renderer::SpriteRender,
+        shrev::EventChannel,
+    };
+    use ncollide2d::shape::Ball;
+    use std::collections::{HashSet, HashMap};
+    
+    use crate::components::*;
+    use crate::events::*;
+    use crate::resources::{PhysicsConfig, GravitySolver, Fragmentation, SimClock, Diagnostics, ConservedQuantities, SpriteRenders};
+    use crate::integrator::BodyState;
+    use crate::tools::is_finite;
+    
+    pub const G: f32 = 0.0001;    // Strength of gravity
+    pub const C: f32 = 800.0;     // Speed of light, only used for the size of black holes
+    
+    // First system of a tick. Moves bodies back to their last physics position,
+    // since their transforms may have been interpolated for rendering since then.
+    pub struct RestoreTransformSystem;
+
+    impl<'a> System<'a> for RestoreTransformSystem {
+        type SystemData = (
+            WriteStorage<'a, Interpolation>,
+            WriteStorage<'a, Transform>,
+        );
+
+        fn run(&mut self, (mut interpolations, mut transforms): Self::SystemData) {
+            for (interpolation, transform) in (&mut interpolations, &mut transforms).join() {
+                transform.set_translation_x(interpolation.current.x);
+                transform.set_translation_y(interpolation.current.y);
+                interpolation.previous = interpolation.current;
+            }
+        }
+    }
+
+    // Last system of a tick. Records where every moving body ended up.
+    pub struct StorePositionSystem;
+
+    impl<'a> System<'a> for StorePositionSystem {
+        type SystemData = (
+            Entities<'a>,
+            WriteStorage<'a, Interpolation>,
+            ReadStorage<'a, Transform>,
+            ReadStorage<'a, Velocity>,
+        );
+
+        fn run(&mut self, (entities, mut interpolations, transforms, velocities): Self::SystemData) {
+            for (entity, transform, _) in (&entities, &transforms, &velocities).join() {
+                let translation = transform.translation();
+                let pos = Point2::new(translation.x, translation.y);
+
+                match interpolations.get_mut(entity) {
+                    Some(interpolation) => interpolation.current = pos,
+                    None => {   // New body, so there is nothing to interpolate from yet
+                        interpolations.insert(entity, Interpolation::at(pos)).expect("Could not insert interpolation.");
+                    },
+                }
+            }
+        }
+    }
+
+    // Moves every body with mass forward by one step, using the integrator chosen in the physics config.
+    pub struct IntegrationSystem;
+    
+    impl<'a> System<'a> for IntegrationSystem {
+        type SystemData = (
+            Entities<'a>,
+            Read<'a, SimClock>,
+            Read<'a, PhysicsConfig>,
+            WriteStorage<'a, Transform>,
+            WriteStorage<'a, Velocity>,
+            WriteStorage<'a, Acceleration>,
+            ReadStorage<'a, Mass>,
+            ReadStorage<'a, Pinned>,
+        );
+    
+        fn run(
+            &mut self,
+            (
+                entities,
+                sim_clock,
+                physics_config,
+                mut transforms,
+                mut velocities,
+                mut accelerations,
+                masses,
+                pinned,
+            ): Self::SystemData
+        ) {
+            let dt = sim_clock.timestep;
+
+            let mut body_entities: Vec<Entity> = Vec::new();
+            let mut bodies: Vec<BodyState> = Vec::new();
+            let mut body_masses: Vec<f32> = Vec::new();
+            let mut body_accelerations: Vec<Vector2<f32>> = Vec::new();
+            let mut body_pinned: Vec<bool> = Vec::new();
+            let mut accelerations_known = true; // New bodies don't have an acceleration from the last step yet
+
+            for (entity, transform, velocity, mass, acceleration, is_pinned) in
+                (&entities, &transforms, &velocities, &masses, (&accelerations).maybe(), pinned.maybe()).join()
+            {
+                let translation = transform.translation();
+                let position = Point2::new(translation.x, translation.y);
+
+                // Keep a broken body from poisoning the field every other body feels
+                if !is_finite(&position.coords) || !is_finite(&velocity.0) || !mass.0.is_finite() {
+                    error!(
+                        "Body {:?} has non-finite state (position: {:?}, velocity: {:?}, mass: {}). Removing it.",
+                        entity, position, velocity.0, mass.0,
+                    );
+                    entities.delete(entity).expect("Could not delete entity.");
+                    continue;
+                }
+
+                body_entities.push(entity);
+                bodies.push(BodyState {
+                    position,
+                    velocity: if is_pinned.is_some() { Vector2::zeros() } else { velocity.0 },
+                });
+                body_masses.push(mass.0);
+                body_pinned.push(is_pinned.is_some());
+
+                match acceleration {
+                    Some(acceleration) => body_accelerations.push(acceleration.0),
+                    None => {
+                        accelerations_known = false;
+                        body_accelerations.push(Vector2::zeros());
+                    },
+                }
+            }
+
+            let (solver, softening) = (physics_config.gravity_solver, physics_config.softening);
+            // Pinned bodies have no velocity or acceleration, so every integrator leaves them where they are
+            let field = |positions: &[Point2<f32>]| {
+                let mut field_accelerations = gravity_accelerations(solver, softening, positions, &body_masses);
+                for (acceleration, is_pinned) in field_accelerations.iter_mut().zip(body_pinned.iter()) {
+                    if *is_pinned {
+                        *acceleration = Vector2::zeros();
+                    }
+                }
+                field_accelerations
+            };
+
+            if !accelerations_known {
+                let positions: Vec<Point2<f32>> = bodies.iter().map(|body| body.position).collect();
+                body_accelerations = field(&positions);
+            }
+
+            physics_config.integrator.integrator().step(&mut bodies, &mut body_accelerations, dt, &field);
+
+            for ((entity, body), acceleration) in body_entities.into_iter().zip(bodies).zip(body_accelerations) {
+                if !is_finite(&body.position.coords) || !is_finite(&body.velocity) {
+                    error!(
+                        "Body {:?} became non-finite during the step (position: {:?}, velocity: {:?}). Removing it.",
+                        entity, body.position, body.velocity,
+                    );
+                    entities.delete(entity).expect("Could not delete entity.");
+                    continue;
+                }
+
+                if let Some(transform) = transforms.get_mut(entity) {
+                    transform.set_translation_x(body.position.x);
+                    transform.set_translation_y(body.position.y);
+                }
+                if let Some(velocity) = velocities.get_mut(entity) {
+                    velocity.0 = body.velocity;
+                }
+
+                if is_finite(&acceleration) {
+                    accelerations.insert(entity, Acceleration(acceleration)).expect("Could not insert acceleration.");
+                } else {
+                    // Probably felt a body that was just removed. Recalculated next step without it.
+                    warn!("Body {:?} has non-finite acceleration {:?}, it will be recalculated.", entity, acceleration);
+                    accelerations.remove(entity);
+                }
+            }
+        }
+    }
+
+    // Ticks between diagnostics log lines
+    const DIAGNOSTICS_LOG_INTERVAL: u64 = 600;
+
+    // Measures energy, momentum and angular momentum every few ticks, to check the simulation is behaving.
+    pub struct DiagnosticsSystem;
+
+    impl<'a> System<'a> for DiagnosticsSystem {
+        type SystemData = (
+            Read<'a, SimClock>,
+            Read<'a, PhysicsConfig>,
+            Write<'a, Diagnostics>,
+            ReadStorage<'a, Transform>,
+            ReadStorage<'a, Velocity>,
+            ReadStorage<'a, Mass>,
+        );
+
+        fn run(&mut self, (sim_clock, physics_config, mut diagnostics, transforms, velocities, masses): Self::SystemData) {
+            let interval = physics_config.diagnostics_interval;
+            if interval == 0 || sim_clock.tick % interval != 0 {
+                return;
+            }
+
+            let bodies: Vec<(Vector2<f64>, Vector2<f64>, f64)> = (&transforms, &velocities, &masses).join()
+                .map(|(transform, velocity, mass)| {
+                    let translation = transform.translation();
+                    (
+                        Vector2::new(translation.x as f64, translation.y as f64),
+                        Vector2::new(velocity.x as f64, velocity.y as f64),
+                        mass.0 as f64,
+                    )
+                }).collect();
+
+            let mut quantities = ConservedQuantities::default();
+            let (g, softening) = (G as f64, physics_config.softening as f64);
+
+            for (i, (pos, vel, mass)) in bodies.iter().enumerate() {
+                // K = 1/2 mv^2
+                quantities.kinetic_energy += 0.5 * mass * vel.norm_squared();
+                // p = mv
+                quantities.momentum += vel * *mass;
+                quantities.momentum_scale += mass * vel.norm();
+                // L = m (r x v)
+                quantities.angular_momentum += mass * (pos.x * vel.y - pos.y * vel.x);
+
+                // U = -GMm/r, softened the same way as the force so that they agree
+                for (other_pos, _, other_mass) in bodies[i+1..].iter() {
+                    let distance = ((other_pos - pos).norm_squared() + softening * softening).sqrt();
+                    quantities.potential_energy -= g * mass * other_mass/distance;
+                }
+            }
+
+            diagnostics.current = quantities;
+            diagnostics.tick = sim_clock.tick;
+            if diagnostics.initial.is_none() {
+                diagnostics.initial = Some(quantities);
+            }
+
+            if sim_clock.tick % DIAGNOSTICS_LOG_INTERVAL < interval {
+                info!(
+                    "Tick {}: E = {:.4e} (drift {:+.3e}), |p| = {:.4e} (drift {:.3e}), L = {:.4e} (drift {:+.3e})",
+                    sim_clock.tick,
+                    quantities.total_energy(), diagnostics.energy_drift(),
+                    quantities.momentum.norm(), diagnostics.momentum_drift(),
+                    quantities.angular_momentum, diagnostics.angular_momentum_drift(),
+                );
+            }
+        }
+    }
+
+    // Gravitational acceleration of every body, when the bodies are at the given positions.
+    pub fn gravity_accelerations(solver: GravitySolver, softening: f32, positions: &[Point2<f32>], masses: &[f32]) -> Vec<Vector2<f32>> {
+        let mut grav_forces = match solver {
+            GravitySolver::DirectSum => direct_sum(positions, masses, softening),
+            GravitySolver::BarnesHut { theta } => barnes_hut(positions, masses, theta, softening),
+        };
+
+        // a = F/m
+        for (grav_force, mass) in grav_forces.iter_mut().zip(masses) {
+            *grav_force /= *mass;
+        }
+        grav_forces
+    }
+
+    fn direct_sum(positions: &[Point2<f32>], masses: &[f32], softening: f32) -> Vec<Vector2<f32>> {
+        // Since the force experienced between to planets is equal and _opposite_ for the other planet,
+        // we only need to calculate the force between a pair.
+        let len = positions.len();
+        let mut grav_forces = vec![Vector2::zeros(); len];
+
+        for i in 0..len.saturating_sub(1) { // For every body except from last
+            for j in i+1..len {   // For every body not done (i) onwards
+                // r is vector from this object to other object
+                let r_vec = positions[j] - positions[i];
+                // grav_force will be experienced by both
+                let grav_force = gravitational_force(r_vec, masses[i], masses[j], softening);
+
+                grav_forces[i] += grav_force;
+                grav_forces[j] -= grav_force;   // -= cause force is applied in opposite direction
+            }
+        }
+
+        grav_forces
+    }
+
+    fn barnes_hut(positions: &[Point2<f32>], masses: &[f32], theta: f32, softening: f32) -> Vec<Vector2<f32>> {
+        use amethyst::ecs::rayon::prelude::*;
+        use crate::quadtree::QuadTree;
+
+        let tree = QuadTree::new(positions, masses);
+
+        // Each body walks the tree independently, so this can be done in parallel
+        (0..positions.len())
+            .into_par_iter()
+            .map(|i| tree.force_on(i, positions, masses, theta, softening))
+            .collect()
+    }
+
+    // F = GMm/r^2
+    // F_vec = (GMm/r^2) r_hat = (GMm/r^3) r_vec
+    // r_vec is the vector from the body feeling the force to the other body.
+    // With Plummer softening r^3 becomes (r^2 + e^2)^(3/2), so the force stays finite as r -> 0
+    // rather than flinging bodies that pass very close to each other.
+    #[inline]
+    pub fn gravitational_force(r_vec: Vector2<f32>, m1: f32, m2: f32, softening: f32) -> Vector2<f32> {
+        let distance_cubed = (r_vec.norm_squared() + softening * softening).powf(1.5);
+        (G * m1 * m2/distance_cubed) * r_vec
+    }
+    
+    // Bodies are only checked for collisions once they have moved, so fast bodies can pass through each other
+    // between two ticks. With continuous collisions on, each body's path over the tick is checked instead.
+    pub struct CollisionDetectionSystem;
+
+    #[derive(Copy, Clone)]
+    struct CollisionBody<'a> {
+        entity: Entity,
+        transform: &'a Transform,
+        collider: &'a Collider,
+        start: Point2<f32>, // Position at the start of the tick
+    }
+
+    impl<'a> CollisionBody<'a> {
+        fn start_isometry(&self) -> Isometry2<f32> {
+            let mut isometry = isometry_of(self.transform);
+            isometry.translation.vector = self.start.coords;
+            isometry
+        }
+
+        // Movement over the tick
+        fn displacement(&self) -> Vector2<f32> {
+            let translation = self.transform.translation();
+            Vector2::new(translation.x, translation.y) - self.start.coords
+        }
+    }
+    
+    impl<'a> System<'a> for CollisionDetectionSystem {
+        type SystemData = (
+            Entities<'a>,
+            Read<'a, PhysicsConfig>,
+            Write<'a, EventChannel<CollisionEvent>>,
+            ReadStorage<'a, Transform>,        // Need write access to all these to add new planets
+            ReadStorage<'a, Collider>,
+            ReadStorage<'a, Interpolation>,
+        );
+    
+        fn run(
+            &mut self, 
+            (
+                entities,
+                physics_config,
+                mut collision_event_channel,
+                transforms,
+                colliders,
+                interpolations,
+            ): Self::SystemData,
+        ) {
+            let continuous = physics_config.continuous_collisions;
+            let mut collision_groups: Vec<CollisionEvent> = Vec::new();
+
+            let bodies: Vec<CollisionBody> = (&entities, &transforms, &colliders, (&interpolations).maybe()).join()
+                .map(|(entity, transform, collider, interpolation)| {
+                    let translation = transform.translation();
+                    CollisionBody {
+                        entity,
+                        transform,
+                        collider,
+                        // Bodies created this tick have no previous position, so treat them as not having moved
+                        start: interpolation.map_or(Point2::new(translation.x, translation.y), |interpolation| interpolation.previous),
+                    }
+                }).collect();
+
+            // Only pairs that pass the broad phase get the (much slower) narrow phase check
+            for (i, j) in Self::broad_phase(&bodies, continuous) {
+                let (body, other) = (bodies[i], bodies[j]);
+
+                let contact_fraction = if continuous {
+                    Self::time_of_impact(&body, &other)
+                } else if body.collider.is_colliding_with(body.transform, other.collider, other.transform) {
+                    Some(1.0)
+                } else {
+                    None
+                };
+
+                if let Some(contact_fraction) = contact_fraction {
+                    Self::put_in_collision_group(&mut collision_groups, body.entity, other.entity, contact_fraction);
+                }
+            }
+
+            // Send event to collision channel
+            collision_event_channel.iter_write(collision_groups);
+        }
+    }
+    
+    impl CollisionDetectionSystem {
+        // Sweep and prune along x. Returns each unordered pair of bodies whose bounding boxes overlap, once.
+        // For continuous collisions the boxes cover the whole path the body took this tick.
+        fn broad_phase(bodies: &[CollisionBody], continuous: bool) -> Vec<(usize, usize)> {
+            use ncollide2d::bounding_volume::BoundingVolume;
+
+            let aabbs: Vec<_> = bodies.iter()
+                .map(|body| {
+                    let aabb = body.collider.aabb(body.transform);
+                    if continuous {
+                        aabb.merged(&body.collider.0.aabb(&body.start_isometry()))
+                    } else {
+                        aabb
+                    }
+                })
+                .collect();
+
+            let mut order: Vec<usize> = (0..bodies.len()).collect();
+            order.sort_unstable_by(|&a, &b| aabbs[a].mins().x.partial_cmp(&aabbs[b].mins().x).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mut pairs = Vec::new();
+            let mut active: Vec<usize> = Vec::new();  // Boxes that the sweep line is currently inside
+
+            for &i in order.iter() {
+                let min_x = aabbs[i].mins().x;
+                active.retain(|&j| aabbs[j].maxs().x >= min_x);
+
+                for &j in active.iter() {
+                    if aabbs[i].intersects(&aabbs[j]) {
+                        pairs.push((j, i));
+                    }
+                }
+                active.push(i);
+            }
+
+            pairs
+        }
+
+        // Treats both bodies as moving in a straight line from their start to end positions over the tick.
+        // Returns how far through the tick they first touched, from 0 to 1.
+        fn time_of_impact(body: &CollisionBody, other: &CollisionBody) -> Option<f32> {
+            use ncollide2d::query;
+
+            query::time_of_impact(
+                &body.start_isometry(),
+                &body.displacement(),
+                &*body.collider.0,
+                &other.start_isometry(),
+                &other.displacement(),
+                &*other.collider.0,
+                1.0,    // Displacements are per tick, so time is in ticks
+                0.0,
+            ).map(|toi| toi.toi)
+        }
+
+        fn put_in_collision_group(collision_groups: &mut Vec<CollisionEvent>, e1: Entity, e2: Entity, contact_fraction: f32) {
+            // If either entity is in an existing group, then add the other to that group.
+            // If they are in different groups, those groups have now touched, so merge them.
+            // Otherwise, make a new group.
+            // The group's contact time is the earliest of any pair in it.
+            let group1 = collision_groups.iter().position(|event| event.group.contains(&e1));
+            let group2 = collision_groups.iter().position(|event| event.group.contains(&e2));
+
+            match (group1, group2) {
+                (Some(g1), Some(g2)) if g1 == g2 => {
+                    info!("{:?} and {:?} already paired.", e1, e2);
+                    collision_groups[g1].add_contact(e1, e2, contact_fraction);
+                },
+                (Some(g1), Some(g2)) => {
+                    info!("Merging collision groups {:?} and {:?}", collision_groups[g1].group, collision_groups[g2].group);
+                    let other = collision_groups.swap_remove(g1.max(g2));
+                    let merged = &mut collision_groups[g1.min(g2)];
+                    merged.group.extend(other.group);
+                    merged.pairs.extend(other.pairs);
+                    merged.contact_fraction = merged.contact_fraction.min(other.contact_fraction);
+                    merged.add_contact(e1, e2, contact_fraction);
+                },
+                (Some(g1), None) => {
+                    info!("Inserting {:?} into group {:?}", e2, collision_groups[g1].group);
+                    collision_groups[g1].group.insert(e2);
+                    collision_groups[g1].add_contact(e1, e2, contact_fraction);
+                },
+                (None, Some(g2)) => {
+                    info!("Inserting {:?} into group {:?}", e1, collision_groups[g2].group);
+                    collision_groups[g2].group.insert(e1);
+                    collision_groups[g2].add_contact(e1, e2, contact_fraction);
+                },
+                (None, None) => {
+                    let mut group = HashSet::with_capacity(2);
+                    group.insert(e1);
+                    group.insert(e2);
+                    collision_groups.push(CollisionEvent {
+                        group,
+                        pairs: vec![(e1, e2)],
+                        contact_fraction,
+                    });
+                },
+            }
+        }
+    }
+
+    pub struct CollisionProcessingSystem {
+        reader_id: ReaderId<CollisionEvent>,
+    }
+
+    impl<'a> System<'a> for CollisionProcessingSystem {
+        type SystemData = (
+            Entities<'a>,
+            Read<'a, EventChannel<CollisionEvent>>,
+            Write<'a, EventChannel<BodyCreationEvent>>,
+            Read<'a, SimClock>,
+            Read<'a, PhysicsConfig>,
+            WriteStorage<'a, Transform>,
+            WriteStorage<'a, Velocity>,
+            WriteStorage<'a, Mass>,
+            WriteStorage<'a, Collider>,
+            ReadStorage<'a, Interpolation>,
+            ReadStorage<'a, CollisionResponse>,
+            ReadStorage<'a, BodyType>,
+            WriteStorage<'a, TidalDebris>,
+        );
+
+        fn run(
+            &mut self,
+            (
+                entities,
+                collision_event_channel,
+                mut body_creation_event_channel,
+                sim_clock,
+                physics_config,
+                mut transforms,
+                mut velocities,
+                mut masses,
+                mut colliders,
+                interpolations,
+                collision_responses,
+                body_types,
+                mut tidal_debris,
+            ): Self::SystemData
+        ) {
+            use crate::entities::body;
+
+            for event in collision_event_channel.read(&mut self.reader_id) {
+                info!("CollisionEvent: {:?}", event);
+
+                // Bodies use the global response unless they have their own
+                let response_of = |entity: &Entity| collision_responses.get(*entity).copied()
+                    .unwrap_or(physics_config.collision_response);
+
+                // Everything in the group has to bounce for it to bounce, otherwise it merges
+                let all_bounce = event.group.iter()
+                    .all(|entity| matches!(response_of(entity), CollisionResponse::Bounce { .. }));
+
+                // Everything is moved back to where it was when the group first touched, part way through the tick
+                let contact_position = |entity: &Entity| {
+                    let translation = transforms.get(*entity).unwrap().translation();
+                    let end = Vector2::new(translation.x, translation.y);
+                    match interpolations.get(*entity) {
+                        Some(interpolation) => interpolation.previous.coords + (end - interpolation.previous.coords) * event.contact_fraction,
+                        None => end,
+                    }
+                };
+                let remaining_time = (1.0 - event.contact_fraction) * sim_clock.timestep;
+
+                // The biggest black hole in the group swallows everything else, whatever their responses
+                let black_hole = event.group.iter()
+                    .filter(|entity| matches!(body_types.get(**entity), Some(BodyType::BlackHole)))
+                    .max_by(|e1, e2| {
+                        let (m1, m2) = (masses.get(**e1).map_or(0.0, |mass| mass.0), masses.get(**e2).map_or(0.0, |mass| mass.0));
+                        m1.partial_cmp(&m2).unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .copied();
+
+                if let Some(black_hole) = black_hole {
+                    let mut r_m_sum: Vector2<f32> = Vector2::zeros();
+                    let mut mass_sum: f32 = 0.0;
+                    let mut momentum_sum: Vector2<f32> = Vector2::zeros();
+
+                    for entity in event.group.iter() {
+                        let mass = masses.get(*entity).map_or(1.0, |mass| mass.0);
+                        let velocity = velocities.get(*entity).map_or(Vector2::zeros(), |velocity| velocity.0);
+
+                        r_m_sum += contact_position(entity) * mass;
+                        mass_sum += mass;
+                        momentum_sum += velocity * mass;
+
+                        if *entity != black_hole {
+                            entities.delete(*entity).expect("Could not delete entity.");
+                        }
+                    }
+
+                    let vel = momentum_sum/mass_sum;
+                    let position = r_m_sum/mass_sum + vel * remaining_time;
+
+                    // The black hole keeps its entity rather than being re-created like a merge.
+                    // Its horizon grows in proportion to mass, not with the cube root like a planet.
+                    let radius = crate::tools::schwarzschild_radius(mass_sum);
+                    if let Some(transform) = transforms.get_mut(black_hole) {
+                        let scale = body::PLANET_SPRITE_RATIO * radius;
+                        transform.set_translation_x(position.x);
+                        transform.set_translation_y(position.y);
+                        transform.set_scale(Vector3::new(scale, scale, 1.0));
+                    }
+                    if let Some(velocity) = velocities.get_mut(black_hole) {
+                        velocity.0 = vel;
+                    }
+                    if let Some(mass) = masses.get_mut(black_hole) {
+                        mass.0 = mass_sum;
+                    }
+                    if let Some(collider) = colliders.get_mut(black_hole) {
+                        *collider = Collider(Box::new(Ball::new(radius)));
+                    }
+                } else if all_bounce {
+                    let mut bodies: HashMap<Entity, BounceBody> = event.group.iter()
+                        .map(|entity| (*entity, BounceBody {
+                            position: contact_position(entity),
+                            velocity: velocities.get(*entity).map_or(Vector2::zeros(), |velocity| velocity.0),
+                            mass: masses.get(*entity).map_or(1.0, |mass| mass.0),
+                            radius: colliders.get(*entity).and_then(|collider| collider.radius()).unwrap_or(0.0),
+                            restitution: match response_of(entity) {
+                                CollisionResponse::Bounce { restitution } => restitution,
+                                CollisionResponse::Merge => 0.0,
+                            },
+                        }))
+                        .collect();
+
+                    Self::bounce(&mut bodies, &event.pairs);
+
+                    // Bodies carry on from the contact point with their new velocities for the rest of the tick.
+                    // Their cached accelerations are left as they are, since they have only moved a small amount.
+                    for (entity, body) in bodies {
+                        let position = body.position + body.velocity * remaining_time;
+                        if let Some(transform) = transforms.get_mut(entity) {
+                            transform.set_translation_x(position.x);
+                            transform.set_translation_y(position.y);
+                        }
+                        if let Some(velocity) = velocities.get_mut(entity) {
+                            velocity.0 = body.velocity;
+                        }
+                    }
+                } else {
+                    // Find centre of mass = new position
+                    // r_com = SUM( m * r ) where r is position vector
+                    let mut r_m_sum: Vector2<f32> = Vector2::zeros();
+                    let mut mass_sum: f32 = 0.0;
+        
+                    // Momentum before = momentum after
+                    let mut momentum_sum: Vector2<f32> = Vector2::zeros();
+
+                    // Sum of mv^2, to find the kinetic energy of the impact
+                    let mut m_v_squared_sum: f32 = 0.0;
+
+                    for entity in event.group.iter() {
+                        // Get mass. Assume mass is 1 if no mass.
+                        let mass = masses.get(*entity)
+                            .unwrap_or(&Mass(1.0));
+                        let velocity: Velocity = velocities.get(*entity).copied()
+                            .unwrap_or(Velocity(Vector2::zeros()));
+        
+                        r_m_sum += contact_position(entity) * mass.0;
+                        mass_sum += mass.0;
+                        momentum_sum += velocity.0 * mass.0;
+                        m_v_squared_sum += mass.0 * velocity.norm_squared();
+                    }
+        
+                    // p = mv, v = p/m
+                    let vel = momentum_sum/mass_sum;
+                    let r_com: Point2<f32> = Point2::from(r_m_sum/mass_sum);
+                    let radius = body::radius_from_mass(mass_sum);
+
+                    // Kinetic energy relative to the centre of mass is what's available to break things up
+                    // SUM( 1/2 m (v - v_com)^2 ) = 1/2 SUM( m v^2 ) - 1/2 M v_com^2
+                    let impact_energy = (0.5 * (m_v_squared_sum - mass_sum * vel.norm_squared())).max(0.0);
+
+                    // The heaviest body swallows the rest, unless the impact breaks everything up
+                    let heaviest = event.group.iter()
+                        .max_by(|e1, e2| {
+                            let (m1, m2) = (masses.get(**e1).map_or(1.0, |mass| mass.0), masses.get(**e2).map_or(1.0, |mass| mass.0));
+                            m1.partial_cmp(&m2).unwrap_or(std::cmp::Ordering::Equal)
+                        })
+                        .copied();
+
+                    match Self::fragment(&physics_config.fragmentation, r_com, vel, mass_sum, radius, impact_energy) {
+                        Some(fragments) => {
+                            for entity in event.group.iter() {
+                                entities.delete(*entity).expect("Could not delete entity.");
+                            }
+
+                            // Everything moves on from the contact point for the rest of the tick
+                            body_creation_event_channel.iter_write(fragments.into_iter().map(|mut fragment| {
+                                fragment.position += fragment.velocity * remaining_time;
+                                fragment
+                            }));
+                        },
+                        None => if let Some(heaviest) = heaviest {
+                            for entity in event.group.iter().filter(|entity| **entity != heaviest) {
+                                entities.delete(*entity).expect("Could not delete entity.");
+                            }
+
+                            // The merged body keeps the heaviest one's entity, so if the new mass takes it over a
+                            // boundary the evolution system sees the change and reports it (e.g. a planet becoming a star).
+                            let position = r_com.coords + vel * remaining_time;
+                            if let Some(transform) = transforms.get_mut(heaviest) {
+                                let scale = body::PLANET_SPRITE_RATIO * radius;
+                                transform.set_translation_x(position.x);
+                                transform.set_translation_y(position.y);
+                                transform.set_scale(Vector3::new(scale, scale, 1.0));
+                            }
+                            if let Some(velocity) = velocities.get_mut(heaviest) {
+                                velocity.0 = vel;
+                            }
+                            if let Some(mass) = masses.get_mut(heaviest) {
+                                mass.0 = mass_sum;
+                            }
+                            if let Some(collider) = colliders.get_mut(heaviest) {
+                                *collider = Collider(Box::new(Ball::new(radius)));
+                            }
+                            // It's a new body now, not a piece of a disrupted one
+                            tidal_debris.remove(heaviest);
+                        },
+                    }
+                }
+            }
+        }
+    }
+
+    struct BounceBody {
+        position: Vector2<f32>,
+        velocity: Vector2<f32>,
+        mass: f32,
+        radius: f32,
+        restitution: f32,
+    }
+
+    impl CollisionProcessingSystem {
+        pub fn new(reader_id: ReaderId<CollisionEvent>) -> Self {
+            Self { reader_id }
+        }
+
+        // Debris from an impact that had more energy than the merged body's gravitational binding energy.
+        // None if the impact wasn't hard enough, or the body is too small to break into pieces.
+        fn fragment(
+            fragmentation: &Fragmentation,
+            position: Point2<f32>,
+            velocity: Vector2<f32>,
+            mass: f32,
+            radius: f32,
+            impact_energy: f32,
+        ) -> Option<Vec<BodyCreationEvent>> {
+            use crate::entities::body;
+
+            if !fragmentation.enabled {
+                return None;
+            }
+
+            let binding_energy = crate::tools::binding_energy(mass, radius);
+            if impact_energy < fragmentation.threshold * binding_energy {
+                return None;
+            }
+
+            let fragment_num = fragmentation.fragment_num(mass);
+            if fragment_num < 2 {
+                return None;
+            }
+
+            // Whatever energy is left after unbinding the body goes into flinging the pieces apart
+            let speed = (2.0 * (impact_energy - binding_energy).max(0.0)/mass).sqrt();
+
+            info!("Impact energy {:.3e} > binding energy {:.3e}, breaking into {} fragments.", impact_energy, binding_energy, fragment_num);
+            Some(body::debris_cloud(&mut rand::thread_rng(), position, velocity, mass, fragment_num, speed))
+        }
+
+        // Resolves each touching pair in turn as a collision between two balls.
+        // Momentum is conserved, and the restitution of the pair (the lower of the two) sets how much of
+        // the speed they approached at they leave with: 1 is perfectly elastic, 0 and they stick.
+        fn bounce(bodies: &mut HashMap<Entity, BounceBody>, pairs: &[(Entity, Entity)]) {
+            for (e1, e2) in pairs.iter() {
+                let (b1, b2) = match (bodies.get(e1), bodies.get(e2)) {
+                    (Some(b1), Some(b2)) => (b1, b2),
+                    _ => continue,
+                };
+
+                let r_vec = b2.position - b1.position;
+                let distance = r_vec.norm();
+                if distance <= std::f32::EPSILON {
+                    continue;   // No way to tell which way they should bounce
+                }
+                let normal = r_vec/distance;
+
+                let (w1, w2) = (1.0/b1.mass, 1.0/b2.mass);  // Lighter bodies get moved more
+                let restitution = b1.restitution.min(b2.restitution);
+
+                // Push them apart so they are just touching
+                let overlap = (b1.radius + b2.radius - distance).max(0.0);
+                let (push1, push2) = (-normal * overlap * w1/(w1 + w2), normal * overlap * w2/(w1 + w2));
+
+                // Impulse along the normal, but only if they are moving towards each other
+                let closing_speed = (b2.velocity - b1.velocity).dot(&normal);
+                let impulse = if closing_speed < 0.0 {
+                    -(1.0 + restitution) * closing_speed/(w1 + w2)
+                } else {
+                    0.0
+                };
+
+                if let Some(b1) = bodies.get_mut(e1) {
+                    b1.position += push1;
+                    b1.velocity -= normal * impulse * w1;
+                }
+                if let Some(b2) = bodies.get_mut(e2) {
+                    b2.position += push2;
+                    b2.velocity += normal * impulse * w2;
+                }
+            }
+        }
+    }
+
+    pub struct CollisionProcessingSystemDesc;
+
+    impl<'a, 'b> SystemDesc<'a, 'b, CollisionProcessingSystem> for CollisionProcessingSystemDesc {
+        fn build(self, world: &mut World) -> CollisionProcessingSystem {
+            <CollisionProcessingSystem as System<'_>>::SystemData::setup(world);
+        
+            let reader_id = world.fetch_mut::<EventChannel<CollisionEvent>>().register_reader();
+            CollisionProcessingSystem::new(reader_id)
+        }
+    }
+
+
+    // Finds bodies that have wandered inside the Roche limit of a star or black hole, where its tides are stronger than
+    // the body's own gravity holding it together.
+    // Bodies already in a collision this tick are left alone, since the collision will have deleted them.
+    pub struct TidalDisruptionSystem {
+        reader_id: ReaderId<CollisionEvent>,
+    }
+
+    impl<'a> System<'a> for TidalDisruptionSystem {
+        type SystemData = (
+            Entities<'a>,
+            Read<'a, PhysicsConfig>,
+            Read<'a, EventChannel<CollisionEvent>>,
+            Write<'a, EventChannel<TidalDisruptionEvent>>,
+            ReadStorage<'a, BodyType>,
+            ReadStorage<'a, Transform>,
+            ReadStorage<'a, Mass>,
+            ReadStorage<'a, Collider>,
+            ReadStorage<'a, TidalDebris>,
+        );
+
+        fn run(
+            &mut self,
+            (
+                entities,
+                physics_config,
+                collision_event_channel,
+                mut tidal_disruption_event_channel,
+                body_types,
+                transforms,
+                masses,
+                colliders,
+                tidal_debris,
+            ): Self::SystemData
+        ) {
+            // Has to be read every tick, even when turned off, so the channel doesn't fill up
+            let colliding: HashSet<Entity> = collision_event_channel.read(&mut self.reader_id)
+                .flat_map(|event| event.group.iter().copied())
+                .collect();
+
+            let tidal_disruption = &physics_config.tidal_disruption;
+            if !tidal_disruption.enabled {
+                return;
+            }
+
+            let body_of = |(entity, transform, mass, collider): (Entity, &Transform, &Mass, &Collider)| {
+                let translation = transform.translation();
+                (entity, Point2::new(translation.x, translation.y), mass.0, collider.radius())
+            };
+
+            let primaries: Vec<_> = (&entities, &body_types, &transforms, &masses, &colliders).join()
+                .filter(|(_, body_type, _, _, _)| matches!(body_type, BodyType::Star | BodyType::BlackHole))
+                .map(|(entity, _, transform, mass, collider)| body_of((entity, transform, mass, collider)))
+                .collect();
+            if primaries.is_empty() {
+                return;
+            }
+
+            let mut events = Vec::new();
+
+            for (body, position, mass, radius) in (&entities, &transforms, &masses, &colliders, !&tidal_debris).join()
+                .map(|(entity, transform, mass, collider, _)| body_of((entity, transform, mass, collider)))
+            {
+                let radius = match radius {
+                    Some(radius) => radius,
+                    None => continue,
+                };
+                if colliding.contains(&body) {
+                    continue;
+                }
+
+                let fragment_num = tidal_disruption.fragment_num(mass);
+                if fragment_num < 2 {
+                    continue;   // Too small to break up any further
+                }
+
+                let primary = primaries.iter()
+                    .filter(|(primary, _, primary_mass, _)| *primary != body && *primary_mass > mass)
+                    .find(|(_, primary_position, primary_mass, primary_radius)| {
+                        let primary_radius = match primary_radius {
+                            Some(primary_radius) => *primary_radius,
+                            None => return false,
+                        };
+                        let roche_limit = tidal_disruption.roche_limit(*primary_mass, primary_radius, mass, radius);
+                        (primary_position - position).norm_squared() < roche_limit * roche_limit
+                    });
+
+                if let Some((primary, _, _, _)) = primary {
+                    events.push(TidalDisruptionEvent {
+                        body,
+                        primary: *primary,
+                        fragment_num,
+                    });
+                }
+            }
+
+            tidal_disruption_event_channel.iter_write(events);
+        }
+    }
+
+    pub struct TidalDisruptionSystemDesc;
+
+    impl<'a, 'b> SystemDesc<'a, 'b, TidalDisruptionSystem> for TidalDisruptionSystemDesc {
+        fn build(self, world: &mut World) -> TidalDisruptionSystem {
+            <TidalDisruptionSystem as System<'_>>::SystemData::setup(world);
+
+            let reader_id = world.fetch_mut::<EventChannel<CollisionEvent>>().register_reader();
+            TidalDisruptionSystem { reader_id }
+        }
+    }
+
+    // Tears disrupted bodies into a line of fragments pointing at the primary.
+    // The line starts off turning with the body's orbit, but the inner fragments orbit faster than the outer ones,
+    // so it gets sheared out into a stream along the orbit, and eventually a ring.
+    pub struct TidalDisruptionProcessingSystem {
+        reader_id: ReaderId<TidalDisruptionEvent>,
+    }
+
+    impl<'a> System<'a> for TidalDisruptionProcessingSystem {
+        type SystemData = (
+            Entities<'a>,
+            Read<'a, EventChannel<TidalDisruptionEvent>>,
+            Write<'a, EventChannel<BodyCreationEvent>>,
+            ReadStorage<'a, Transform>,
+            ReadStorage<'a, Velocity>,
+            ReadStorage<'a, Mass>,
+        );
+
+        fn run(
+            &mut self,
+            (
+                entities,
+                tidal_disruption_event_channel,
+                mut body_creation_event_channel,
+                transforms,
+                velocities,
+                masses,
+            ): Self::SystemData
+        ) {
+            use crate::entities::body::PLANET_DENSITY;
+
+            for event in tidal_disruption_event_channel.read(&mut self.reader_id) {
+                let (transform, velocity, mass) = match (transforms.get(event.body), velocities.get(event.body), masses.get(event.body)) {
+                    (Some(transform), Some(velocity), Some(mass)) => (transform, velocity, mass.0),
+                    _ => continue,
+                };
+                let primary_transform = match transforms.get(event.primary) {
+                    Some(primary_transform) => primary_transform,
+                    None => continue,
+                };
+                let primary_velocity = velocities.get(event.primary).map_or(Vector2::zeros(), |velocity| velocity.0);
+
+                info!("{:?} torn apart by the tides of {:?} into {} fragments.", event.body, event.primary, event.fragment_num);
+
+                let position = Vector2::new(transform.translation().x, transform.translation().y);
+                let r_vec = position - Vector2::new(primary_transform.translation().x, primary_transform.translation().y);
+                let v_rel = velocity.0 - primary_velocity;
+                let direction = r_vec.normalize();
+
+                // Angular velocity of the orbit, w = (r x v)/r^2
+                let angular_velocity = (r_vec.x * v_rel.y - r_vec.y * v_rel.x)/r_vec.norm_squared();
+
+                let fragment_mass = mass/event.fragment_num as f32;
+                let fragment_radius = crate::tools::inverse_volume_of_sphere(fragment_mass/PLANET_DENSITY);
+                let spacing = 2.2 * fragment_radius;    // Just far enough apart that they don't touch
+
+                // Offsets are symmetric about the body, so centre of mass and momentum don't change
+                let middle = (event.fragment_num - 1) as f32/2.0;
+                body_creation_event_channel.iter_write((0..event.fragment_num).map(|i| {
+                    let offset = direction * spacing * (i as f32 - middle);
+                    // v = w x offset
+                    let rotation = Vector2::new(-offset.y, offset.x) * angular_velocity;
+
+                    BodyCreationEvent {
+                        body_type: BodyType::from_mass(fragment_mass),
+                        position: Point2::from(position + offset),
+                        velocity: velocity.0 + rotation,
+                        mass: fragment_mass,
+                        radius: fragment_radius,
+                        collision_response: None,
+                        tidal_debris: true,  // Or they would be torn apart again next tick, still being inside the Roche limit
+                    }
+                }));
+
+                entities.delete(event.body).expect("Could not delete entity.");
+            }
+        }
+    }
+
+    pub struct TidalDisruptionProcessingSystemDesc;
+
+    impl<'a, 'b> SystemDesc<'a, 'b, TidalDisruptionProcessingSystem> for TidalDisruptionProcessingSystemDesc {
+        fn build(self, world: &mut World) -> TidalDisruptionProcessingSystem {
+            <TidalDisruptionProcessingSystem as System<'_>>::SystemData::setup(world);
+
+            let reader_id = world.fetch_mut::<EventChannel<TidalDisruptionEvent>>().register_reader();
+            TidalDisruptionProcessingSystem { reader_id }
+        }
+    }
+
+    // Re-classifies bodies whose mass has changed since they were created, e.g. a black hole that has been feeding.
+    // Bodies keep whatever type they were created with until then, so scenarios can still choose their own.
+    pub struct BodyEvolutionSystem {
+        reader_id: ReaderId<ComponentEvent>,
+        modified: BitSet,
+    }
+
+    impl<'a> System<'a> for BodyEvolutionSystem {
+        type SystemData = (
+            Entities<'a>,
+            Write<'a, EventChannel<BodyTypeChanged>>,
+            Read<'a, SpriteRenders>,
+            ReadStorage<'a, Mass>,
+            WriteStorage<'a, BodyType>,
+            WriteStorage<'a, Collider>,
+            WriteStorage<'a, Transform>,
+            WriteStorage<'a, SpriteRender>,
+        );
+
+        fn run(
+            &mut self,
+            (
+                entities,
+                mut body_type_changed_channel,
+                renders,
+                masses,
+                mut body_types,
+                mut colliders,
+                mut transforms,
+                mut sprite_renders,
+            ): Self::SystemData
+        ) {
+            use crate::entities::body;
+
+            self.modified.clear();
+            for event in masses.channel().read(&mut self.reader_id) {
+                if let ComponentEvent::Modified(id) = event {
+                    self.modified.add(*id);
+                }
+            }
+
+            let mut events = Vec::new();
+
+            for (entity, mass, body_type, _) in (&entities, &masses, &mut body_types, &self.modified).join() {
+                let new_type = BodyType::from_mass(mass.0);
+                if std::mem::discriminant(body_type) == std::mem::discriminant(&new_type) {
+                    continue;
+                }
+
+                info!("{:?} has become a {:?} (was a {:?}).", entity, new_type, body_type);
+                events.push(BodyTypeChanged {
+                    body: entity,
+                    from: *body_type,
+                    to: new_type,
+                });
+                *body_type = new_type;
+
+                let radius = body::radius_from_mass(mass.0);
+                if let Some(collider) = colliders.get_mut(entity) {
+                    *collider = Collider(Box::new(Ball::new(radius)));
+                }
+                if let Some(transform) = transforms.get_mut(entity) {
+                    let scale = body::PLANET_SPRITE_RATIO * radius;
+                    transform.set_scale(Vector3::new(scale, scale, 1.0));
+                }
+                if let Some(render) = new_type.get_render(&renders) {
+                    sprite_renders.insert(entity, render).expect("Could not insert sprite render.");
+                }
+            }
+
+            body_type_changed_channel.iter_write(events);
+        }
+    }
+
+    pub struct BodyEvolutionSystemDesc;
+
+    impl<'a, 'b> SystemDesc<'a, 'b, BodyEvolutionSystem> for BodyEvolutionSystemDesc {
+        fn build(self, world: &mut World) -> BodyEvolutionSystem {
+            <BodyEvolutionSystem as System<'_>>::SystemData::setup(world);
+
+            let reader_id = WriteStorage::<Mass>::fetch(world).register_reader();
+            BodyEvolutionSystem {
+                reader_id,
+                modified: BitSet::new(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+        use crate::integrator::IntegratorKind;
+
+        fn assert_forces_close(expected: &[Vector2<f32>], actual: &[Vector2<f32>], tolerance: f32) {
+            for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                let error = (expected - actual).norm()/expected.norm();
+                assert!(error < tolerance, "Body {}: expected {:?}, got {:?} (relative error {})", i, expected, actual, error);
+            }
+        }
+
+        #[test]
+        fn barnes_hut_with_zero_theta_matches_direct_sum() {
+            let mut rng = StdRng::seed_from_u64(0);
+            let positions: Vec<_> = (0..200)
+                .map(|_| Point2::new(rng.gen_range(-1000.0, 1000.0), rng.gen_range(-1000.0, 1000.0)))
+                .collect();
+            let masses: Vec<_> = (0..200).map(|_| rng.gen_range(1.0, 1000.0)).collect();
+
+            assert_forces_close(
+                &direct_sum(&positions, &masses, 1.0),
+                &barnes_hut(&positions, &masses, 0.0, 1.0),
+                1e-3,
+            );
+        }
+
+        #[test]
+        fn barnes_hut_does_not_pull_a_body_with_its_own_mass() {
+            // The centre of mass of all three is far enough from the first body that the root node passes the
+            // opening test at theta = 1, but the root contains the first body so still has to be opened.
+            let positions = [Point2::new(0.0, 0.0), Point2::new(100.0, 100.0), Point2::new(100.0, 99.0)];
+            let masses = [500.0, 1000.0, 1000.0];
+
+            assert_forces_close(
+                &direct_sum(&positions, &masses, 0.0)[..1],
+                &barnes_hut(&positions, &masses, 1.0, 0.0)[..1],
+                1e-2,
+            );
+        }
+
+        // Largest relative change in separation and total energy of a light body on a circular orbit around a heavy one
+        fn circular_orbit_drift(integrator: IntegratorKind, periods: f32, steps_per_period: f32) -> (f32, f32) {
+            let (parent_mass, mass, radius) = (1e8, 1.0, 200.0);
+            let masses = [parent_mass, mass];
+            let speed = crate::tools::circular_orbit_speed(parent_mass, radius);
+            let period = 2.0 * std::f32::consts::PI * radius/speed;
+            let dt = period/steps_per_period;
+
+            let mut bodies = [
+                BodyState { position: Point2::new(0.0, 0.0), velocity: Vector2::zeros() },
+                BodyState { position: Point2::new(radius, 0.0), velocity: Vector2::new(0.0, speed) },
+            ];
+            let energy = |bodies: &[BodyState]| {
+                let separation = (bodies[1].position - bodies[0].position).norm();
+                bodies.iter().zip(&masses).map(|(body, mass)| 0.5 * mass * body.velocity.norm_squared()).sum::<f32>()
+                    - G * parent_mass * mass/separation
+            };
+            let initial_energy = energy(&bodies);
+
+            let field = |positions: &[Point2<f32>]| gravity_accelerations(GravitySolver::DirectSum, 0.0, positions, &masses);
+            let mut accelerations = field(&[bodies[0].position, bodies[1].position]);
+
+            let (mut radius_drift, mut energy_drift): (f32, f32) = (0.0, 0.0);
+            for _ in 0..(periods * steps_per_period) as usize {
+                integrator.integrator().step(&mut bodies, &mut accelerations, dt, &field);
+
+                let separation = (bodies[1].position - bodies[0].position).norm();
+                radius_drift = radius_drift.max((separation/radius - 1.0).abs());
+                energy_drift = energy_drift.max((energy(&bodies)/initial_energy - 1.0).abs());
+            }
+            (radius_drift, energy_drift)
+        }
+
+        #[test]
+        fn circular_orbit_stays_circular_under_velocity_verlet() {
+            let (radius_drift, energy_drift) = circular_orbit_drift(IntegratorKind::VelocityVerlet, 10.0, 200.0);
+            assert!(radius_drift < 1e-3, "Radius drifted by {}", radius_drift);
+            assert!(energy_drift < 1e-4, "Energy drifted by {}", energy_drift);
+
+            let (euler_radius_drift, euler_energy_drift) = circular_orbit_drift(IntegratorKind::SemiImplicitEuler, 10.0, 200.0);
+            assert!(euler_radius_drift > radius_drift, "Euler radius drift {} <= Verlet {}", euler_radius_drift, radius_drift);
+            assert!(euler_energy_drift > energy_drift, "Euler energy drift {} <= Verlet {}", euler_energy_drift, energy_drift);
         }
     }
 }
@@ -1,4 +1,9 @@
 use std::f32::consts::PI;
+use amethyst::{
+    core::{math::{Vector2, Point3}, Transform},
+    renderer::Camera,
+    window::ScreenDimensions,
+};
 
 pub fn volume_of_sphere(r: f32) -> f32 {
     4.0/3.0 * PI * r.powi(3)
@@ -11,4 +16,39 @@ pub fn inverse_volume_of_sphere(v: f32) -> f32 {
 #[inline]
 pub fn circular_orbit_speed(parent_mass: f32, radius: f32) -> f32 {
     (crate::systems::physics::G * parent_mass/radius).sqrt()
-}
\ No newline at end of file
+}
+
+// Energy needed to pull a uniform sphere apart against its own gravity
+// U = 3GM^2/5R
+pub fn binding_energy(mass: f32, radius: f32) -> f32 {
+    3.0 * crate::systems::physics::G * mass * mass/(5.0 * radius)
+}
+
+// Radius of the event horizon of a black hole
+// r = 2GM/c^2
+pub fn schwarzschild_radius(mass: f32) -> f32 {
+    use crate::systems::physics::{G, C};
+
+    2.0 * G * mass/(C * C)
+}
+
+// Converts a mouse position (in physical pixels) to a position in the world, through the camera's projection.
+// Works whatever the size of the window or the camera's position and zoom.
+pub fn screen_to_world(
+    screen_pos: (f32, f32),
+    camera: &Camera,
+    camera_transform: &Transform,
+    screen_dimensions: &ScreenDimensions,
+) -> Vector2<f32> {
+    let world_pos = camera.projection().screen_to_world_point(
+        Point3::new(screen_pos.0, screen_pos.1, 0.0),
+        screen_dimensions.diagonal(),
+        camera_transform,
+    );
+    Vector2::new(world_pos.x, world_pos.y)
+}
+
+#[inline]
+pub fn is_finite(v: &Vector2<f32>) -> bool {
+    v.x.is_finite() && v.y.is_finite()
+}